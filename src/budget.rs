@@ -0,0 +1,82 @@
+//! The budget module bounds how long a search may run, by wall-clock time
+//! and by the number of nodes it visits.
+
+use std::time::{Duration, Instant};
+
+/// the number of visits between two looks at the clock, which costs far more than a visit
+const CLOCK_INTERVAL: usize = 1024;
+
+/// The `Budget` struct counts visited nodes against an optional deadline
+/// and an optional node limit
+pub struct Budget {
+    deadline: Option<Instant>,
+    node_limit: Option<usize>,
+    nodes: usize,
+    /// whether the deadline had passed the last time the clock was read
+    late: bool,
+}
+
+impl Budget {
+    /// Returns a new budget starting now
+    pub fn new(time_limit: Option<Duration>, node_limit: Option<usize>) -> Budget {
+        Budget {
+            deadline: time_limit.map(|limit| Instant::now() + limit),
+            node_limit,
+            nodes: 0,
+            late: false,
+        }
+    }
+
+    /// Counts a visited node, returning false once the budget is spent, so
+    /// that a node limit of `n` allows exactly `n` visits.
+    /// The deadline is only checked every `CLOCK_INTERVAL` visits
+    pub fn visit(&mut self) -> bool {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(CLOCK_INTERVAL) {
+            self.late = self.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        }
+
+        !self.is_spent()
+    }
+
+    /// Returns the number of nodes visited
+    pub fn nodes(&self) -> usize {
+        self.nodes
+    }
+
+    /// Returns if the deadline had passed at the last check or the node limit was exceeded
+    fn is_spent(&self) -> bool {
+        self.late || self.node_limit.is_some_and(|limit| self.nodes > limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_at_the_node_limit() {
+        let mut budget = Budget::new(None, Some(3));
+        assert!(budget.visit());
+        assert!(budget.visit());
+        assert!(budget.visit());
+        assert!(!budget.visit());
+        assert_eq!(budget.nodes(), 4);
+
+        let mut budget = Budget::new(None, Some(0));
+        assert!(!budget.visit());
+    }
+
+    #[test]
+    fn stops_within_an_interval_of_the_deadline() {
+        let mut budget = Budget::new(Some(Duration::ZERO), None);
+        let visits = (0..10 * CLOCK_INTERVAL).take_while(|_| budget.visit()).count();
+        assert_eq!(visits, CLOCK_INTERVAL - 1);
+    }
+
+    #[test]
+    fn is_unbounded_without_limits() {
+        let mut budget = Budget::new(None, None);
+        assert!((0..10 * CLOCK_INTERVAL).all(|_| budget.visit()));
+    }
+}
@@ -0,0 +1,542 @@
+//! The config module holds the tunable parameters of the guesser, which can be
+//! built in code or loaded from a json file instead of being edited in the source.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde_json::Value;
+
+/// Returns the loaded default config, included at compile-time like the frequencies
+fn load_default_config() -> Value {
+    let text = include_str!("resources/guesser.json");
+    serde_json::from_str(text)
+        .expect("Cannot parse the content in 'guesser.json'!")
+}
+
+/// Returns the json value of a file
+fn load_json(path: &Path) -> Result<Value, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|error| format!("cannot read '{}': {}", path.display(), error))?;
+
+    serde_json::from_str(&text)
+        .map_err(|error| format!("cannot parse '{}': {}", path.display(), error))
+}
+
+/// Returns an inclusive `[min, max]` range from a json pair, or the default
+fn parse_range(value: &Value, key: &str, default: (usize, usize)) -> Result<(usize, usize), String> {
+    let range = &value[key];
+    if range.is_null() {
+        return Ok(default);
+    }
+
+    match range.as_array().map(|pair| pair.as_slice()) {
+        Some([min, max]) => match (min.as_u64(), max.as_u64()) {
+            (Some(min), Some(max)) => Ok((min as usize, max as usize)),
+            _ => Err(format!("'{}' must be a pair of non-negative integers", key)),
+        },
+        _ => Err(format!("'{}' must be a pair of non-negative integers", key)),
+    }
+}
+
+/// Returns a list of characters from a json array of single character strings
+fn parse_chars(value: &Value, key: &str) -> Result<Option<Vec<char>>, String> {
+    let list = &value[key];
+    if list.is_null() {
+        return Ok(None);
+    }
+
+    let list = list.as_array()
+        .ok_or(format!("'{}' must be a list of characters", key))?;
+
+    let mut chars = vec![];
+    for item in list.iter() {
+        let mut text = item.as_str().unwrap_or("").chars();
+        match (text.next(), text.next()) {
+            (Some(c), None) => chars.push(c),
+            _ => return Err(format!("'{}' must be a list of characters", key)),
+        }
+    }
+
+    Ok(Some(chars))
+}
+
+/// What the guesser knows when deciding whether to make an exploratory guess
+pub struct FailState<'a> {
+    pub difficulty: usize,
+    pub attempt: usize,
+    /// number of positions not yet green, where the `=` always counts as placed
+    pub unknown: usize,
+    /// the characters known to be in the secret at least once
+    pub present: &'a [char],
+    /// the operators known to be in the secret
+    pub operators: &'a [char],
+}
+
+/// A single policy rule, an exploratory guess is made when every condition holds
+#[derive(Clone, Debug)]
+pub struct FailRule {
+    /// inclusive range of difficulties the rule applies to
+    pub difficulty: (usize, usize),
+    /// the attempt number, where the initial guess is attempt 0
+    pub attempt: usize,
+    /// inclusive range of positions not yet green
+    pub unknown: (usize, usize),
+    /// characters that must be known to be present
+    pub requires: Vec<char>,
+    /// if given, the exact set of operators known to be present
+    pub operators: Option<Vec<char>>,
+}
+
+impl FailRule {
+    fn from_json(value: &Value) -> Result<FailRule, String> {
+        let attempt = value["attempt"].as_u64()
+            .ok_or("a rule needs a non-negative 'attempt'")?;
+
+        Ok(FailRule {
+            difficulty: parse_range(value, "difficulty", (7, 15))?,
+            attempt: attempt as usize,
+            unknown: parse_range(value, "unknown", (0, usize::MAX))?,
+            requires: parse_chars(value, "requires")?.unwrap_or_default(),
+            operators: parse_chars(value, "operators")?,
+        })
+    }
+
+    fn matches(&self, state: &FailState) -> bool {
+        let in_range = |(min, max): (usize, usize), value| min <= value && value <= max;
+
+        if !in_range(self.difficulty, state.difficulty)
+            || self.attempt != state.attempt
+            || !in_range(self.unknown, state.unknown) {
+            return false;
+        }
+
+        if self.requires.iter().any(|c| !state.present.contains(c)) {
+            return false;
+        }
+
+        if let Some(operators) = &self.operators {
+            if operators.len() != state.operators.len()
+                || operators.iter().any(|op| !state.operators.contains(op)) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// The policy choosing between exploratory (`backtrack_fail`) and candidate guesses
+#[derive(Clone, Debug)]
+pub enum FailPolicy {
+    /// explore whenever any of the rules match
+    Rules(Vec<FailRule>),
+    /// explore whenever the exploratory guess is expected to leave fewer
+    /// candidates than the candidate guess
+    Reduction,
+}
+
+impl FailPolicy {
+    /// Returns the policy described by a json object, either
+    /// `{"mode": "rules", "rules": [...]}` or `{"mode": "reduction"}`
+    pub fn from_json(value: &Value) -> Result<FailPolicy, String> {
+        match value["mode"].as_str() {
+            Some("rules") => {
+                let rules = value["rules"].as_array()
+                    .ok_or("a rules policy needs a list of 'rules'")?;
+
+                let rules = rules.iter()
+                    .map(FailRule::from_json)
+                    .collect::<Result<Vec<FailRule>, String>>()?;
+
+                Ok(FailPolicy::Rules(rules))
+            }
+            Some("reduction") => Ok(FailPolicy::Reduction),
+            _ => Err("the policy 'mode' must be \"rules\" or \"reduction\"".to_string()),
+        }
+    }
+
+    /// Returns if a rules policy asks for an exploratory guess,
+    /// always false for a reduction policy
+    pub fn matches(&self, state: &FailState) -> bool {
+        match self {
+            FailPolicy::Rules(rules) => rules.iter().any(|rule| rule.matches(state)),
+            FailPolicy::Reduction => false,
+        }
+    }
+}
+
+/// The order in which candidate guesses are searched
+#[derive(Clone, Debug)]
+pub enum SearchOrder {
+    /// left to right over the characters, in frequency order
+    Backtrack,
+    /// over the results that fit the hints, then the expressions evaluating to them
+    Results,
+}
+
+impl SearchOrder {
+    fn from_json(value: &Value) -> Result<SearchOrder, String> {
+        match value.as_str() {
+            Some("backtrack") => Ok(SearchOrder::Backtrack),
+            Some("results") => Ok(SearchOrder::Results),
+            _ => Err("'search' must be \"backtrack\" or \"results\"".to_string()),
+        }
+    }
+}
+
+/// How the guesser places the equality operator while it is not green
+#[derive(Clone, Debug)]
+pub enum EqualityMode {
+    /// assume the configured position, treating it as green
+    Assume,
+    /// try every position some consistent secret has, the most common first
+    Weighted,
+}
+
+impl EqualityMode {
+    fn from_json(value: &Value) -> Result<EqualityMode, String> {
+        match value.as_str() {
+            Some("assume") => Ok(EqualityMode::Assume),
+            Some("weighted") => Ok(EqualityMode::Weighted),
+            _ => Err("'equality' must be \"assume\" or \"weighted\"".to_string()),
+        }
+    }
+}
+
+/// The `GuesserConfig` struct gathers the tuning parameters of the `Guesser`
+#[derive(Clone, Debug)]
+pub struct GuesserConfig {
+    /// scale of the random noise added when sorting characters
+    pub noise: f64,
+    /// weight of the positional frequency against the used character counts
+    pub frequency_weight: f64,
+    /// how the `=` is placed while it is not green
+    pub equality: EqualityMode,
+    /// the assumed `=` position while it is not green, indexed by difficulty - 7
+    pub equality_positions: [usize; 9],
+    /// the characters the initial guess picks from, each added over a difficulty range
+    pub initial_characters: Vec<((usize, usize), Vec<char>)>,
+    /// when to make exploratory guesses
+    pub fail_policy: FailPolicy,
+    /// the order in which candidate guesses are searched
+    pub search: SearchOrder,
+    /// whether to prune partial expressions whose result cannot have the right length
+    pub prune: bool,
+    /// the time the search may take per guess
+    pub time_limit: Option<Duration>,
+    /// the number of nodes the search may visit per guess
+    pub node_limit: Option<usize>,
+    /// if given, look two guesses ahead instead of guessing greedily
+    pub lookahead: Option<LookaheadConfig>,
+    /// whether every guess must respect the revealed hints, so no exploratory guesses
+    pub hard_mode: bool,
+    /// the directory the full feedback matrices are cached in, if any
+    pub cache_directory: Option<PathBuf>,
+}
+
+impl Default for GuesserConfig {
+    fn default() -> GuesserConfig {
+        GuesserConfig::from_json(&Value::Object(Default::default()))
+            .expect("Invalid config in 'guesser.json'!")
+    }
+}
+
+impl GuesserConfig {
+    /// Returns the config described by a json object, where every missing key
+    /// keeps its value from the default 'guesser.json' and every other key is refused
+    pub fn from_json(value: &Value) -> Result<GuesserConfig, String> {
+        let overrides = value.as_object()
+            .ok_or("the config must be a json object")?;
+
+        let mut config = load_default_config();
+        for (key, item) in overrides.iter() {
+            if config.get(key).is_none() {
+                return Err(format!("'{}' is not a config key", key));
+            }
+
+            config[key] = item.clone();
+        }
+
+        let number = |key: &str| config[key].as_f64()
+            .ok_or(format!("'{}' must be a number", key));
+
+        let limit = |key: &str| -> Result<Option<u64>, String> {
+            match &config[key] {
+                Value::Null => Ok(None),
+                item => item.as_u64()
+                    .map(Some)
+                    .ok_or(format!("'{}' must be a non-negative integer or null", key)),
+            }
+        };
+
+        let initial_characters = config["initial_characters"].as_array()
+            .ok_or("'initial_characters' must be a list")?
+            .iter()
+            .map(|item| Ok((
+                parse_range(item, "difficulty", (7, 15))?,
+                parse_chars(item, "add")?.unwrap_or_default(),
+            )))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(GuesserConfig {
+            noise: number("noise")?,
+            frequency_weight: number("frequency_weight")?,
+            equality: EqualityMode::from_json(&config["equality"])?,
+            equality_positions: parse_equality_positions(&config["equality_positions"])?,
+            initial_characters,
+            fail_policy: FailPolicy::from_json(&config["fail_policy"])?,
+            search: SearchOrder::from_json(&config["search"])?,
+            prune: config["prune"].as_bool().ok_or("'prune' must be true or false")?,
+            time_limit: limit("time_ms")?.map(Duration::from_millis),
+            node_limit: limit("nodes")?.map(|nodes| nodes as usize),
+            lookahead: LookaheadConfig::from_json(&config["lookahead"])?,
+            hard_mode: config["hard_mode"].as_bool().ok_or("'hard_mode' must be true or false")?,
+            cache_directory: match &config["cache_directory"] {
+                Value::Null => None,
+                item => Some(PathBuf::from(item.as_str().ok_or("'cache_directory' must be a path or null")?)),
+            },
+        })
+    }
+
+    /// Returns the config in a json file
+    pub fn load(path: &Path) -> Result<GuesserConfig, String> {
+        GuesserConfig::from_json(&load_json(path)?)
+    }
+
+    /// Returns the assumed `=` position for a difficulty
+    pub fn equality_position(&self, difficulty: usize) -> usize {
+        self.equality_positions[difficulty - 7]
+    }
+
+    /// Returns the characters the initial guess picks from for a difficulty
+    pub fn initial_characters(&self, difficulty: usize) -> Vec<char> {
+        let mut chars = vec![];
+        for ((min, max), add) in self.initial_characters.iter() {
+            if *min <= difficulty && difficulty <= *max {
+                chars.extend(add.iter());
+            }
+        }
+
+        chars
+    }
+}
+
+/// Returns the nine assumed `=` positions, checking each leaves room for a result
+fn parse_equality_positions(value: &Value) -> Result<[usize; 9], String> {
+    let error = "'equality_positions' must be a list of 9 positions, one for each difficulty";
+
+    let list = value.as_array().ok_or(error)?;
+    if list.len() != 9 {
+        return Err(error.to_string());
+    }
+
+    let mut positions = [0; 9];
+    for (i, item) in list.iter().enumerate() {
+        let position = item.as_u64().ok_or(error)? as usize;
+        let difficulty = i + 7;
+        if position < 5 || position > difficulty - 2 {
+            return Err(format!(
+                "the equality position {} does not fit difficulty {}",
+                position, difficulty,
+            ));
+        }
+
+        positions[i] = position;
+    }
+
+    Ok(positions)
+}
+
+/// The settings of the two-ply lookahead search
+#[derive(Clone, Debug)]
+pub struct LookaheadConfig {
+    /// number of guesses that are looked ahead from
+    pub top_k: usize,
+    /// number of candidates sampled as guesses at each ply
+    pub sample: usize,
+    /// the time the search may take per guess
+    pub time_limit: Option<Duration>,
+    /// the number of feedback evaluations the search may make per guess
+    pub node_limit: Option<usize>,
+}
+
+impl LookaheadConfig {
+    /// Returns the lookahead settings of a json object, or None for null
+    fn from_json(value: &Value) -> Result<Option<LookaheadConfig>, String> {
+        if value.is_null() {
+            return Ok(None);
+        }
+
+        if !value.is_object() {
+            return Err("'lookahead' must be an object or null".to_string());
+        }
+
+        let optional = |key: &str| -> Result<Option<u64>, String> {
+            match &value[key] {
+                Value::Null => Ok(None),
+                item => item.as_u64()
+                    .map(Some)
+                    .ok_or(format!("'lookahead.{}' must be a non-negative integer", key)),
+            }
+        };
+
+        Ok(Some(LookaheadConfig {
+            top_k: optional("top_k")?.unwrap_or(8) as usize,
+            sample: optional("sample")?.unwrap_or(200) as usize,
+            time_limit: optional("time_ms")?.map(Duration::from_millis),
+            node_limit: optional("nodes")?.map(|nodes| nodes as usize),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Returns the exploratory decision of the `should_fail` branches the default rules replace
+    fn baseline_should_fail(difficulty: usize, attempt: usize, unknown: usize, present: &[char], operators: &[char]) -> bool {
+        let only_modulo = operators == ['%'];
+        match difficulty {
+            7 => false,
+            8 => attempt == 1,
+            9 => attempt == 1 || (attempt == 2 && present.contains(&'%') && unknown <= 4),
+            10 => attempt == 1 || (attempt == 3 && only_modulo && 1 < unknown && unknown <= 3),
+            _ => attempt == 1
+                || (attempt == 2 && unknown <= 2)
+                || (attempt == 3 && only_modulo && 1 < unknown && unknown <= 3),
+        }
+    }
+
+    #[test]
+    fn default_rules_match_the_baseline_branches() {
+        let policy = GuesserConfig::default().fail_policy;
+        let operator_sets: [&[char]; 5] = [&[], &['%'], &['+'], &['%', '+'], &['-', '*']];
+
+        for difficulty in 7..=15 {
+            for attempt in 0..=5 {
+                for unknown in 0..=difficulty {
+                    for operators in operator_sets.iter() {
+                        let mut present = vec!['1', '='];
+                        present.extend(operators.iter());
+
+                        let state = FailState { difficulty, attempt, unknown, present: &present, operators };
+                        assert_eq!(
+                            policy.matches(&state),
+                            baseline_should_fail(difficulty, attempt, unknown, &present, operators),
+                            "difficulty {} attempt {} unknown {} operators {:?}",
+                            difficulty, attempt, unknown, operators,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rules_need_every_condition() {
+        let rule = FailRule::from_json(&json!({
+            "difficulty": [9, 10], "attempt": 2, "unknown": [1, 3], "requires": ["7"], "operators": ["+", "%"],
+        })).unwrap();
+
+        let state = |difficulty, attempt, unknown, present: &'static [char], operators: &'static [char]| {
+            FailState { difficulty, attempt, unknown, present, operators }
+        };
+
+        assert!(rule.matches(&state(9, 2, 3, &['7', '+', '%'], &['%', '+'])));
+        assert!(!rule.matches(&state(11, 2, 3, &['7', '+', '%'], &['+', '%'])));
+        assert!(!rule.matches(&state(9, 1, 3, &['7', '+', '%'], &['+', '%'])));
+        assert!(!rule.matches(&state(9, 2, 4, &['7', '+', '%'], &['+', '%'])));
+        assert!(!rule.matches(&state(9, 2, 3, &['+', '%'], &['+', '%'])));
+        // the operators must be exactly the given set
+        assert!(!rule.matches(&state(9, 2, 3, &['7', '+'], &['+'])));
+        assert!(!rule.matches(&state(9, 2, 3, &['7', '+', '%', '-'], &['+', '%', '-'])));
+    }
+
+    #[test]
+    fn policies_parse_from_json() {
+        assert!(matches!(FailPolicy::from_json(&json!({"mode": "reduction"})), Ok(FailPolicy::Reduction)));
+
+        match FailPolicy::from_json(&json!({"mode": "rules", "rules": [{"attempt": 1}]})) {
+            Ok(FailPolicy::Rules(rules)) => {
+                assert_eq!(rules.len(), 1);
+                assert_eq!(rules[0].difficulty, (7, 15));
+                assert_eq!(rules[0].unknown, (0, usize::MAX));
+                assert!(rules[0].requires.is_empty());
+                assert!(rules[0].operators.is_none());
+            }
+            other => panic!("unexpected policy {:?}", other),
+        }
+
+        assert!(FailPolicy::from_json(&json!({"mode": "always"})).is_err());
+        assert!(FailPolicy::from_json(&json!({"mode": "rules"})).is_err());
+        assert!(FailPolicy::from_json(&json!({"mode": "rules", "rules": [{"difficulty": [8, 9]}]})).is_err());
+        assert!(FailPolicy::from_json(&json!({"mode": "rules", "rules": [{"attempt": 1, "unknown": [2]}]})).is_err());
+        assert!(FailPolicy::from_json(&json!({"mode": "rules", "rules": [{"attempt": 1, "requires": ["%%"]}]})).is_err());
+    }
+
+    #[test]
+    fn overrides_keep_the_other_defaults() {
+        let config = GuesserConfig::from_json(&json!({
+            "noise": 0.5,
+            "search": "results",
+            "time_ms": null,
+            "nodes": 200,
+            "lookahead": {"top_k": 3},
+            "fail_policy": {"mode": "reduction"},
+        })).unwrap();
+
+        assert_eq!(config.noise, 0.5);
+        assert!(matches!(config.search, SearchOrder::Results));
+        assert_eq!(config.time_limit, None);
+        assert_eq!(config.node_limit, Some(200));
+        assert!(matches!(config.fail_policy, FailPolicy::Reduction));
+
+        let lookahead = config.lookahead.unwrap();
+        assert_eq!((lookahead.top_k, lookahead.sample), (3, 200));
+
+        let default = GuesserConfig::default();
+        assert_eq!(config.frequency_weight, default.frequency_weight);
+        assert_eq!(config.equality_positions, default.equality_positions);
+        assert_eq!(config.prune, default.prune);
+        assert_eq!(default.time_limit, Some(Duration::from_millis(1000)));
+    }
+
+    #[test]
+    fn unknown_keys_and_bad_values_are_refused() {
+        for overrides in [
+            json!({"noize": 0.5}),
+            json!({"time_limit": 100}),
+            json!({"noise": "loud"}),
+            json!({"prune": 1}),
+            json!({"nodes": -1}),
+            json!({"search": "random"}),
+            json!({"equality": "guess"}),
+            json!({"lookahead": 3}),
+            json!({"lookahead": {"top_k": -3}}),
+            json!({"initial_characters": [{"add": ["10"]}]}),
+            json!({"cache_directory": 7}),
+            json!([1, 2]),
+        ] {
+            assert!(GuesserConfig::from_json(&overrides).is_err(), "{}", overrides);
+        }
+
+        let error = GuesserConfig::from_json(&json!({"noize": 0.5})).unwrap_err();
+        assert!(error.contains("noize"), "{}", error);
+    }
+
+    #[test]
+    fn equality_positions_must_leave_room_for_a_result() {
+        let positions = |list: Value| GuesserConfig::from_json(&json!({"equality_positions": list}));
+
+        let config = positions(json!([5, 5, 5, 5, 5, 5, 5, 5, 5])).unwrap();
+        assert_eq!(config.equality_position(15), 5);
+
+        // the largest position of each difficulty leaves a two digit result
+        assert!(positions(json!([5, 6, 7, 8, 9, 10, 11, 12, 13])).is_ok());
+        assert!(positions(json!([6, 6, 7, 8, 8, 8, 8, 8, 8])).is_err());
+        assert!(positions(json!([4, 6, 7, 8, 8, 8, 8, 8, 8])).is_err());
+        assert!(positions(json!([5, 6, 7, 8, 8, 8, 8, 8, 14])).is_err());
+        assert!(positions(json!([5, 6, 7, 8, 8, 8, 8, 8])).is_err());
+    }
+}
@@ -0,0 +1,221 @@
+//! The daily module derives the secret of the day from the date and length
+//! alone, so that everyone gets the same puzzle without any shared service.
+
+use std::collections::{HashSet, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::game::all_secrets;
+
+/// the first day with a daily puzzle, as days since 1970-01-01 (2024-01-01)
+const FIRST_DAY: i64 = 19723;
+
+/// the default number of days within which a secret is never repeated
+pub const WINDOW: usize = 365;
+
+/// The `Puzzle` struct is the daily puzzle of one date and length
+pub struct Puzzle {
+    /// the puzzle number, counting from 1 on the first day
+    pub number: i64,
+    /// the date, as YYYY-MM-DD
+    pub date: String,
+    pub secret: String,
+}
+
+/// Returns the days since 1970-01-01 of a YYYY-MM-DD date
+pub fn parse_date(text: &str) -> Result<i64, String> {
+    let invalid = || format!("'{}' is not a date of the form YYYY-MM-DD", text);
+
+    let parts: Vec<&str> = text.split('-').collect();
+    if parts.len() != 3 || parts[0].len() != 4 || parts[1].len() != 2 || parts[2].len() != 2 {
+        return Err(invalid());
+    }
+
+    let year: i64 = parts[0].parse().map_err(|_| invalid())?;
+    let month: i64 = parts[1].parse().map_err(|_| invalid())?;
+    let day: i64 = parts[2].parse().map_err(|_| invalid())?;
+    if !(1..=12).contains(&month) {
+        return Err(format!("'{}' is not a date, there is no month {}", text, month));
+    }
+
+    if day < 1 || day > days_in_month(year, month) {
+        return Err(format!(
+            "'{}' is not a date, {}-{:02} has {} days", text, year, month, days_in_month(year, month),
+        ));
+    }
+
+    Ok(days_from_civil(year, month, day))
+}
+
+/// Returns today as days since 1970-01-01, in UTC
+pub fn today() -> i64 {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+
+    (seconds / 86400) as i64
+}
+
+/// Returns the YYYY-MM-DD date of a number of days since 1970-01-01
+pub fn format_date(days: i64) -> String {
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Returns the puzzle of a day and length (7-15). Each day's secret is picked
+/// by a hash of the day and length, skipping any secret used in the previous
+/// `window - 1` days, so the days are walked from the first one
+pub fn puzzle(day: i64, length: usize, window: usize) -> Result<Puzzle, String> {
+    if !(7..=15).contains(&length) {
+        return Err(format!("there is no daily puzzle of length {}, only 7 to 15", length));
+    }
+
+    if day < FIRST_DAY {
+        return Err(format!("the first daily puzzle is on {}", format_date(FIRST_DAY)));
+    }
+
+    let secrets = all_secrets(length);
+    if window == 0 || window > secrets.len() {
+        return Err(format!(
+            "the window must be between 1 and {} days for length {}", secrets.len(), length,
+        ));
+    }
+
+    let chosen = chosen_secrets(day, length, secrets.len(), window);
+
+    Ok(Puzzle {
+        number: day - FIRST_DAY + 1,
+        date: format_date(day),
+        secret: secrets[*chosen.last().unwrap()].clone(),
+    })
+}
+
+/// Returns the index among `count` secrets picked on every day from the first
+/// one to `day`, none of which repeats one of the previous `window - 1` days
+fn chosen_secrets(day: i64, length: usize, count: usize, window: usize) -> Vec<usize> {
+    let mut recent: VecDeque<usize> = VecDeque::new();
+    let mut used: HashSet<usize> = HashSet::new();
+    let mut chosen = vec![];
+
+    for current in FIRST_DAY..=day {
+        // probe past the secrets of the window, there is always one free
+        let mut index = (mix(current as u64 * 16 + length as u64) % count as u64) as usize;
+        while used.contains(&index) {
+            index = (index + 1) % count;
+        }
+
+        chosen.push(index);
+        recent.push_back(index);
+        used.insert(index);
+        if recent.len() == window {
+            used.remove(&recent.pop_front().unwrap());
+        }
+    }
+
+    chosen
+}
+
+/// Returns the splitmix64 hash of a number, stable across platforms and builds
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Returns the days since 1970-01-01 of a proleptic gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // count from March, so that the leap day ends the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+/// Returns the (year, month, day) of a number of days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+
+    (year_of_era + era * 400 + if month <= 2 { 1 } else { 0 }, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_same_day_and_length_give_the_same_secret() {
+        let day = parse_date("2026-10-18").unwrap();
+        for length in [7, 8] {
+            let first = puzzle(day, length, WINDOW).unwrap();
+            let again = puzzle(day, length, WINDOW).unwrap();
+            assert_eq!(first.secret, again.secret);
+            assert_eq!(first.secret.len(), length);
+            assert_eq!((first.number, first.date.as_str()), (1022, "2026-10-18"));
+        }
+
+        assert_ne!(puzzle(day, 8, WINDOW).unwrap().secret, puzzle(day + 1, 8, WINDOW).unwrap().secret);
+    }
+
+    #[test]
+    fn secrets_do_not_repeat_within_the_window() {
+        // a few secrets and many days, so that the probing is needed
+        for (count, window) in [(20, 20), (50, 7), (1000, 365)] {
+            let chosen = chosen_secrets(FIRST_DAY + 2000, 8, count, window);
+            assert_eq!(chosen.len(), 2001);
+
+            for days in chosen.windows(window) {
+                let distinct: HashSet<&usize> = days.iter().collect();
+                assert_eq!(distinct.len(), window, "{:?}", days);
+            }
+        }
+    }
+
+    #[test]
+    fn dates_round_trip_across_leap_years() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2024, 1, 1), FIRST_DAY);
+
+        for days in days_from_civil(1896, 1, 1)..days_from_civil(2104, 12, 31) {
+            let (year, month, day) = civil_from_days(days);
+            assert!(day >= 1 && day <= days_in_month(year, month), "{}", days);
+            assert_eq!(days_from_civil(year, month, day), days);
+            assert_eq!(parse_date(&format_date(days)), Ok(days));
+        }
+
+        // 1900 and 2100 are not leap years, 2000 is
+        assert_eq!(format_date(days_from_civil(1900, 2, 28) + 1), "1900-03-01");
+        assert_eq!(format_date(days_from_civil(2000, 2, 28) + 1), "2000-02-29");
+        assert_eq!(format_date(days_from_civil(2100, 2, 28) + 1), "2100-03-01");
+    }
+
+    #[test]
+    fn dates_that_do_not_exist_are_refused() {
+        assert_eq!(parse_date("2026-02-29"), Err("'2026-02-29' is not a date, 2026-02 has 28 days".to_string()));
+        assert_eq!(parse_date("2026-13-01"), Err("'2026-13-01' is not a date, there is no month 13".to_string()));
+        assert!(parse_date("2028-02-29").is_ok());
+        assert!(parse_date("2026-4-01").unwrap_err().contains("of the form YYYY-MM-DD"));
+        assert!(parse_date("today").unwrap_err().contains("of the form YYYY-MM-DD"));
+    }
+}
@@ -0,0 +1,117 @@
+//! The equations module indexes every expression of a length by its result,
+//! so that a search can pick the results that fit the hints first and only
+//! then expand the expressions that evaluate to them.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use lazy_static::lazy_static;
+use crate::game::{digits, for_each_expression, OPERATORS};
+
+lazy_static! {
+    // the indices built so far, by expression length
+    static ref INDICES: Mutex<HashMap<usize, Arc<EquationIndex>>> = Mutex::new(HashMap::new());
+}
+
+/// A compact expression, the three numbers and two operator indices
+#[derive(Clone, Copy)]
+struct Expression {
+    nums: [u8; 3],
+    ops: [u8; 2],
+}
+
+impl Expression {
+    fn text(&self) -> String {
+        format!(
+            "{}{}{}{}{}",
+            self.nums[0],
+            OPERATORS[self.ops[0] as usize],
+            self.nums[1],
+            OPERATORS[self.ops[1] as usize],
+            self.nums[2],
+        )
+    }
+}
+
+/// The `EquationIndex` struct maps every positive result to the expressions
+/// of a single length that evaluate to it
+pub struct EquationIndex {
+    by_result: HashMap<i32, Vec<Expression>>,
+}
+
+impl EquationIndex {
+    /// Returns the index of every expression of `expression_length` characters
+    pub fn build(expression_length: usize) -> EquationIndex {
+        let mut by_result: HashMap<i32, Vec<Expression>> = HashMap::new();
+
+        for_each_expression(expression_length..=expression_length, |_, nums, ops, outcome| {
+            by_result.entry(outcome).or_default().push(Expression {
+                nums: nums.map(|num| num as u8),
+                ops: ops.map(|op| op as u8),
+            });
+        });
+
+        EquationIndex { by_result }
+    }
+
+    /// Returns the shared index of an expression length, building it on first use
+    pub fn for_length(expression_length: usize) -> Arc<EquationIndex> {
+        let mut indices = INDICES.lock().unwrap();
+        indices.entry(expression_length)
+            .or_insert_with(|| Arc::new(EquationIndex::build(expression_length)))
+            .clone()
+    }
+
+    /// Returns every result with `result_length` digits, in no particular order
+    pub fn results(&self, result_length: usize) -> Vec<i32> {
+        self.by_result.keys()
+            .filter(|result| digits(**result) == result_length)
+            .copied()
+            .collect()
+    }
+
+    /// Returns the expressions that evaluate to `result`
+    pub fn expressions(&self, result: i32) -> impl Iterator<Item=String> + '_ {
+        self.by_result.get(&result)
+            .into_iter()
+            .flatten()
+            .map(|expression| expression.text())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::fast_eval;
+
+    #[test]
+    fn expressions_evaluate_to_their_result() {
+        for expression_length in [5, 7] {
+            let index = EquationIndex::build(expression_length);
+
+            let mut size = 0;
+            for result_length in 1..=6 {
+                for result in index.results(result_length) {
+                    for expression in index.expressions(result) {
+                        assert_eq!(expression.len(), expression_length, "{}", expression);
+                        assert_eq!(fast_eval(&expression), Some(result), "{}", expression);
+                        size += 1;
+                    }
+                }
+            }
+
+            let mut expected = 0;
+            for_each_expression(expression_length..=expression_length, |_, _, _, _| expected += 1);
+            assert_eq!(size, expected);
+        }
+    }
+
+    #[test]
+    fn results_have_the_asked_length() {
+        let index = EquationIndex::for_length(6);
+        for result in index.results(3) {
+            assert!((100..1000).contains(&result), "{}", result);
+        }
+
+        assert_eq!(index.expressions(-1).count(), 0);
+    }
+}
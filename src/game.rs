@@ -1,12 +1,17 @@
-use std::cmp::max;
+use std::cmp::{max, min};
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::iter::{Iterator, zip};
+use std::ops::RangeInclusive;
+use std::sync::Mutex;
 use lazy_static::lazy_static;
 use rand::distributions::{Distribution, Uniform};
+use rand::seq::SliceRandom;
+use crate::matrix::{pattern, pattern_id};
+use crate::referee::{Referee, Rejection};
 
 /// The games module contains code needed to run a typical
 /// FoCdle game, with error checking and info and such.
-
 // global variables
 // because performance
 // im sure there is a better way to do this
@@ -24,6 +29,9 @@ lazy_static! {
         ('*', 2),
         ('%', 3),
     ]);
+
+    // the number of secrets with the equality sign at each position, by difficulty
+    static ref EQUALITY_COUNTS: Mutex<HashMap<usize, Vec<usize>>> = Mutex::new(HashMap::new());
 }
 
 
@@ -35,7 +43,7 @@ fn run(left: Option<i32>, right: Option<i32>, op: i32) -> Option<i32> {
 
     let left = left.unwrap();
     let right = right.unwrap();
-    return match op {
+    match op {
         0 => Some(left + right),
         1 => Some(left - right),
         2 => Some(left * right),
@@ -48,7 +56,7 @@ fn run(left: Option<i32>, right: Option<i32>, op: i32) -> Option<i32> {
         }
 
         _ => None
-    };
+    }
 }
 
 /// Returns the evaluated option on the valid FoCdle expression
@@ -59,8 +67,6 @@ fn run(left: Option<i32>, right: Option<i32>, op: i32) -> Option<i32> {
 /// # Return
 /// An option containing None if the expression is invalid, and the result otherwise
 pub fn fast_eval(expression: &str) -> Option<i32> {
-    const PREC: [i32; 4] = [0, 0, 1, 1];
-
     // was the last digit op
     let mut was_op = true;
     // tracking nums
@@ -78,7 +84,7 @@ pub fn fast_eval(expression: &str) -> Option<i32> {
         let c: char = expression.chars().nth(i).unwrap();
 
         // parse integer
-        if '0' <= c && c <= '9' {
+        if c.is_ascii_digit() {
             if !was_op {
                 return None;
             }
@@ -93,7 +99,7 @@ pub fn fast_eval(expression: &str) -> Option<i32> {
             if i + 1 < expression.len() {
                 let c1 = expression.chars().nth(i + 1).unwrap();
 
-                if '0' <= c1 && c1 <= '9' {
+                if c1.is_ascii_digit() {
                     nums[nums_i] = (c.to_digit(10).unwrap() * 10
                         + c1.to_digit(10).unwrap()) as i32;
                     nums_i += 1;
@@ -122,6 +128,14 @@ pub fn fast_eval(expression: &str) -> Option<i32> {
         i += 1;
     }
 
+    eval_parts(nums, ops)
+}
+
+/// Returns the evaluated option on three numbers and two operator codes,
+/// following the precedence of `fast_eval`
+fn eval_parts(nums: [i32; 3], ops: [i32; 2]) -> Option<i32> {
+    const PREC: [i32; 4] = [0, 0, 1, 1];
+
     // if eval second op first
     if PREC[ops[1] as usize] > PREC[ops[0] as usize] {
         return run(
@@ -136,7 +150,7 @@ pub fn fast_eval(expression: &str) -> Option<i32> {
     }
 
     // else if eval first op first
-    return run(
+    run(
         run(
             Some(nums[0]),
             Some(nums[1]),
@@ -144,26 +158,154 @@ pub fn fast_eval(expression: &str) -> Option<i32> {
         ),
         Some(nums[2]),
         ops[1],
-    );
+    )
 }
 
 
+/// An inclusive range of possible values
+type Interval = (i64, i64);
+
+/// Returns the interval of `left op right`, with the op codes of `fast_eval`,
+/// where the right interval is always positive
+fn interval_run(left: Interval, right: Interval, op: i32) -> Interval {
+    match op {
+        0 => (left.0 + right.0, left.1 + right.1),
+        1 => (left.0 - right.1, left.1 - right.0),
+        2 => {
+            let products = [left.0 * right.0, left.0 * right.1, left.1 * right.0, left.1 * right.1];
+            (*products.iter().min().unwrap(), *products.iter().max().unwrap())
+        }
+        _ => {
+            // the remainder takes the sign of the left side, and is smaller than the right
+            let largest = right.1 - 1;
+            (
+                if left.0 < 0 { -largest.min(-left.0) } else { 0 },
+                if left.1 > 0 { largest.min(left.1) } else { 0 },
+            )
+        }
+    }
+}
+
+/// Returns the interval of three number intervals and two operator codes,
+/// following the precedence of `fast_eval`
+fn interval_parts(nums: [Interval; 3], ops: [i32; 2]) -> Interval {
+    const PREC: [i32; 4] = [0, 0, 1, 1];
+
+    if PREC[ops[1] as usize] > PREC[ops[0] as usize] {
+        return interval_run(nums[0], interval_run(nums[1], nums[2], ops[1]), ops[0]);
+    }
+
+    interval_run(interval_run(nums[0], nums[1], ops[0]), nums[2], ops[1])
+}
+
+/// Returns if some completion of the partial `prefix` into an expression of
+/// `expression_length` characters, with three numbers of one or two digits
+/// and two operators, could evaluate to a result of `result_length` digits
+pub fn result_may_fit(prefix: &[char], expression_length: usize, result_length: usize) -> bool {
+    // split the prefix into numbers and operators
+    let mut nums: Vec<i64> = Vec::with_capacity(3);
+    let mut ops: Vec<i32> = Vec::with_capacity(2);
+    let mut open = false;
+    for c in prefix.iter() {
+        match c.to_digit(10) {
+            Some(digit) if open => {
+                let last = nums.last_mut().unwrap();
+                *last = *last * 10 + digit as i64;
+                open = false;
+            }
+            Some(digit) => {
+                if nums.len() == 3 {
+                    return false;
+                }
+
+                nums.push(digit as i64);
+                open = true;
+            }
+            None => {
+                if ops.len() == 2 {
+                    return false;
+                }
+
+                ops.push(EVAL_MAPPING[c]);
+                open = false;
+            }
+        }
+    }
+
+    // the choices for each number, as (interval, characters still to place),
+    // where a started single digit may still grow and a fresh number has one or two digits
+    let mut choices = [[((1, 9), 1), ((10, 99), 2)]; 3];
+    let mut counts = [2; 3];
+    for (i, num) in nums.iter().enumerate() {
+        choices[i][0] = ((*num, *num), 0);
+        counts[i] = 1;
+    }
+    if open {
+        let num = nums[nums.len() - 1];
+        choices[nums.len() - 1][1] = ((num * 10, num * 10 + 9), 1);
+        counts[nums.len() - 1] = 2;
+    }
+
+    let target: Interval = (10i64.pow(result_length as u32 - 1), 10i64.pow(result_length as u32) - 1);
+    let remaining = expression_length - prefix.len();
+    let ops_left = 2 - ops.len();
+
+    // try every combination of number sizes that fills the remaining characters
+    let combinations: usize = counts.iter().product();
+    for combination in 0..combinations {
+        let mut rest = combination;
+        let mut used = ops_left;
+        let mut intervals = [(0, 0); 3];
+        for i in 0..3 {
+            let (interval, length) = choices[i][rest % counts[i]];
+            rest /= counts[i];
+            intervals[i] = interval;
+            used += length;
+        }
+
+        if used != remaining {
+            continue;
+        }
+
+        // try every remaining operator
+        for unknown in 0..4usize.pow(ops_left as u32) {
+            let mut all_ops = [0; 2];
+            for (i, op) in all_ops.iter_mut().enumerate() {
+                *op = if i < ops.len() {
+                    ops[i]
+                } else {
+                    ((unknown >> (2 * (i - ops.len()))) & 3) as i32
+                };
+            }
+
+            let (low, high) = interval_parts(intervals, all_ops);
+            if low <= target.1 && target.0 <= high {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
 /// focdle color info colors
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Color {
-    GREEN,
-    YELLOW,
-    GREY,
+    Green,
+    Yellow,
+    Grey,
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct ColorInfo {
-    index: usize,
-    chara: char,
-    color: Color,
+    pub index: usize,
+    pub chara: char,
+    pub color: Color,
 }
 
 pub type AllInfo = Vec<Vec<ColorInfo>>;
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct InfoLookup {
     pub correct: HashSet<usize>,
     pub incorrect: HashSet<usize>,
@@ -171,37 +313,33 @@ pub struct InfoLookup {
     pub max: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct InfoIndex {
+    pub difficulty: usize,
     pub lookup: HashMap<char, InfoLookup>,
+    /// the characters allowed at each position, derived by `propagate`
+    pub allowed: Vec<HashSet<char>>,
 }
 
 impl InfoIndex {
-    pub fn new() -> InfoIndex {
-        InfoIndex {
-            lookup: HashMap::new()
-        }
-    }
-
-    /// Returns the InfoIndex based on the given difficulty
-    /// and info 2d array
-    pub fn build(difficulty: usize, info: &AllInfo) -> InfoIndex {
+    /// Returns the InfoIndex of a game with no guesses made yet
+    pub fn new(difficulty: usize) -> InfoIndex {
         let mut lookup: HashMap<char, InfoLookup> = HashMap::new();
 
         // initiate table
         // starting with numbers
         for key in NUMS.iter() {
-            lookup.insert(key.clone(), InfoLookup {
+            lookup.insert(*key, InfoLookup {
                 correct: HashSet::new(),
                 incorrect: HashSet::new(),
                 min: 0,
-                max: (if *key != '0' { difficulty - 3 } else { difficulty - 7 }) as usize,
+                max: (if *key != '0' { difficulty - 3 } else { difficulty - 7 }),
             });
         }
 
         // then operators
         for key in OPERATORS.iter() {
-            lookup.insert(key.clone(), InfoLookup {
+            lookup.insert(*key, InfoLookup {
                 correct: HashSet::new(),
                 incorrect: HashSet::new(),
                 min: 0,
@@ -216,53 +354,82 @@ impl InfoIndex {
             max: 1,
         });
 
+        let mut index = InfoIndex {
+            difficulty,
+            lookup,
+            allowed: vec![],
+        };
+        index.propagate();
+
+        index
+    }
 
-        // for each past guess, include color info
+    /// Returns the InfoIndex based on the given difficulty
+    /// and info 2d array
+    pub fn build(difficulty: usize, info: &AllInfo) -> InfoIndex {
+        let mut index = InfoIndex::new(difficulty);
         for guess in info {
-            let mut character_freqs: HashMap<char, usize> = HashMap::new();
-            let mut maxed_characters: HashSet<char> = HashSet::new();
+            index.observe(guess);
+        }
 
-            // add info information
-            for info in guess {
-                let ColorInfo { index, chara, color } = info;
+        index
+    }
 
-                match color {
-                    Color::GREEN => {
-                        lookup.get_mut(chara).unwrap().correct.insert(*index);
-                        *character_freqs.entry(*chara).or_insert(0) += 1;
-                    }
+    /// Folds the colors of one more guess into the index
+    pub fn observe(&mut self, guess: &[ColorInfo]) {
+        let lookup = &mut self.lookup;
+        let mut character_freqs: HashMap<char, usize> = HashMap::new();
+        let mut maxed_characters: HashSet<char> = HashSet::new();
 
-                    Color::YELLOW => {
-                        lookup.get_mut(chara).unwrap().incorrect.insert(*index);
-                        *character_freqs.entry(*chara).or_insert(0) += 1;
-                    }
+        // add info information
+        for info in guess {
+            let ColorInfo { index, chara, color } = info;
 
-                    Color::GREY => {
-                        lookup.get_mut(chara).unwrap().incorrect.insert(*index);
-                        maxed_characters.insert(chara.clone());
-                    }
+            match color {
+                Color::Green => {
+                    lookup.get_mut(chara).unwrap().correct.insert(*index);
+                    *character_freqs.entry(*chara).or_insert(0) += 1;
                 }
-            }
 
-            // update minmax values
-            for key in CHARS.iter() {
-                let key_lookup = lookup.get_mut(key).unwrap();
+                Color::Yellow => {
+                    lookup.get_mut(chara).unwrap().incorrect.insert(*index);
+                    *character_freqs.entry(*chara).or_insert(0) += 1;
+                }
 
-                if key_lookup.min == key_lookup.max {
-                    continue;
+                Color::Grey => {
+                    lookup.get_mut(chara).unwrap().incorrect.insert(*index);
+                    maxed_characters.insert(*chara);
                 }
+            }
+        }
 
-                key_lookup.min = max(
-                    key_lookup.min,
-                    *character_freqs.get(key).unwrap_or(&0),
-                );
+        // update minmax values
+        for key in CHARS.iter() {
+            let key_lookup = lookup.get_mut(key).unwrap();
 
-                if maxed_characters.contains(&key) {
-                    key_lookup.max = key_lookup.min;
-                }
+            if key_lookup.min == key_lookup.max {
+                continue;
+            }
+
+            key_lookup.min = max(
+                key_lookup.min,
+                *character_freqs.get(key).unwrap_or(&0),
+            );
+
+            if maxed_characters.contains(key) {
+                key_lookup.max = key_lookup.min;
             }
         }
 
+        self.restrict_counts();
+        self.propagate();
+    }
+
+    /// Tightens the operator and digit counts using the totals of the secret.
+    /// Only ever lowers a max, so that it can run after every observed guess
+    fn restrict_counts(&mut self) {
+        let lookup = &mut self.lookup;
+
         // improve lookup on operators
         let mut found = false;
         let mut correct_operators = HashSet::new();
@@ -297,7 +464,7 @@ impl InfoIndex {
                 let lookup_op = lookup.get_mut(op).unwrap();
 
                 if correct_operators.contains(op) {
-                    lookup_op.max = 1;
+                    lookup_op.max = min(lookup_op.max, 1);
                 } else {
                     lookup_op.min = 0;
                     lookup_op.max = 0;
@@ -309,31 +476,137 @@ impl InfoIndex {
                 let lookup_op = lookup.get_mut(op).unwrap();
 
                 if !correct_operators.contains(op) {
-                    lookup_op.max = 1;
+                    lookup_op.max = min(lookup_op.max, 1);
                 }
             }
         }
 
         // constraint digits and max digits
         let mut total_min = 0;
-        let mut total_max = 0;
         for (key, lu) in lookup.iter() {
             if NUMS.contains(key) {
                 total_min += lu.min;
-                total_max += lu.max;
             }
         }
 
-        let max_digits = (difficulty - 3) as usize;
+        let max_digits = self.difficulty - 3;
         for (key, lu) in lookup.iter_mut() {
             if NUMS.contains(key) && lu.min < lu.max {
-                lu.max = max_digits - (total_min - lu.min);
+                // contradicting rows can claim more digits than fit
+                lu.max = min(lu.max, max_digits.saturating_sub(total_min - lu.min));
             }
         }
+    }
+
+    /// Derives the characters allowed at every position from the lookup,
+    /// applying the rules of a valid equation until nothing changes
+    pub fn propagate(&mut self) {
+        let difficulty = self.difficulty;
+        let symbols: HashSet<char> = HashSet::from_iter(OPERATORS.iter().chain(['='].iter()).copied());
+        let is_digits = |set: &HashSet<char>| !set.is_empty() && set.iter().all(|c| NUMS.contains(c));
+        let is_symbols = |set: &HashSet<char>| !set.is_empty() && set.is_subset(&symbols);
+
+        // the rules only ever narrow, so an earlier propagation is a sound start
+        let mut allowed: Vec<HashSet<char>> = if self.allowed.len() == difficulty {
+            self.allowed.clone()
+        } else {
+            vec![HashSet::from_iter(CHARS.iter().copied()); difficulty]
+        };
 
-        InfoIndex {
-            lookup
+        loop {
+            let before = allowed.clone();
+
+            // the hints themselves
+            for (key, lookup) in self.lookup.iter() {
+                for correct in lookup.correct.iter() {
+                    allowed[*correct].retain(|c| c == key);
+                }
+
+                for incorrect in lookup.incorrect.iter() {
+                    allowed[*incorrect].remove(key);
+                }
+            }
+
+            // the first number starts with a non-zero digit, and the result ends with a digit
+            allowed[0].retain(|c| NUMS.contains(c) && *c != '0');
+            allowed[difficulty - 1].retain(|c| NUMS.contains(c));
+
+            // the expression takes at least 5 characters, and the result at least 1
+            for (position, chars) in allowed.iter_mut().enumerate() {
+                if position < 5 || position + 2 > difficulty {
+                    chars.remove(&'=');
+                }
+            }
+
+            // the counts, a character is either used up, or has exactly as many places as it needs
+            for (key, lookup) in self.lookup.iter() {
+                let fixed = allowed.iter().filter(|chars| chars.len() == 1 && chars.contains(key)).count();
+                if fixed >= lookup.max {
+                    for chars in allowed.iter_mut() {
+                        if chars.len() > 1 {
+                            chars.remove(key);
+                        }
+                    }
+                }
+
+                let possible: Vec<usize> = (0..difficulty).filter(|p| allowed[*p].contains(key)).collect();
+                if lookup.min > 0 && possible.len() == lookup.min {
+                    for position in possible {
+                        allowed[position].retain(|c| c == key);
+                    }
+                }
+            }
+
+            // symbols are surrounded by numbers, which never start with a zero
+            for position in 0..difficulty {
+                if !is_symbols(&allowed[position]) {
+                    continue;
+                }
+
+                if position > 0 {
+                    allowed[position - 1].retain(|c| !symbols.contains(c));
+                }
+
+                if position + 1 < difficulty {
+                    allowed[position + 1].retain(|c| !symbols.contains(c) && *c != '0');
+                }
+            }
+
+            // there are exactly two operators, and only in the expression
+            let equality = (0..difficulty).find(|p| allowed[*p].len() == 1 && allowed[*p].contains(&'='));
+            let expression_length = equality.unwrap_or(difficulty);
+            if let Some(equality) = equality {
+                for chars in allowed[equality + 1..].iter_mut() {
+                    chars.retain(|c| NUMS.contains(c));
+                }
+            }
+
+            let operators: Vec<usize> = (0..expression_length)
+                .filter(|p| !allowed[*p].is_empty() && allowed[*p].iter().all(|c| OPERATORS.contains(c)))
+                .collect();
+            if operators.len() == 2 {
+                for (position, chars) in allowed.iter_mut().enumerate() {
+                    if !operators.contains(&position) {
+                        chars.retain(|c| !OPERATORS.contains(c));
+                    }
+                }
+            }
+
+            // numbers in the expression have at most two digits, and the
+            // expression runs at least up to the first place `=` could be
+            let earliest = (0..difficulty).find(|p| allowed[*p].contains(&'=')).unwrap_or(difficulty);
+            for position in 2..earliest {
+                if is_digits(&allowed[position - 1]) && is_digits(&allowed[position - 2]) {
+                    allowed[position].retain(|c| !NUMS.contains(c));
+                }
+            }
+
+            if allowed == before {
+                break;
+            }
         }
+
+        self.allowed = allowed;
     }
 }
 
@@ -343,7 +616,7 @@ fn str_frequency(text: &str, default: &[char]) -> HashMap<char, usize> {
 
     // create default
     for key in default.iter() {
-        frequencies.insert(key.clone(), 0);
+        frequencies.insert(*key, 0);
     }
 
     for key in text.chars() {
@@ -362,6 +635,13 @@ pub fn passes_restrictions(
     guess: &str,
     index: &InfoIndex,
 ) -> bool {
+    // check the propagated position sets
+    for (chars, c) in index.allowed.iter().zip(guess.chars()) {
+        if !chars.contains(&c) {
+            return false;
+        }
+    }
+
     // create guess character frequencies
     let guess_frequency = str_frequency(guess, &CHARS);
 
@@ -409,8 +689,71 @@ pub fn passes_restrictions(
     true
 }
 
+/// Returns whether `guess` respects every hint revealed by the rows of
+/// `history`, as hard mode requires: greens kept in place, yellows and greys
+/// moved away, and each character used at least as often as a row revealed
+/// it, and exactly as often when the row also made it grey
+pub fn respects_hints(guess: &str, history: &AllInfo) -> bool {
+    let guess_frequency = str_frequency(guess, &CHARS);
+    let guess: Vec<char> = guess.chars().collect();
+
+    history.iter().all(|row| {
+        let mut revealed: HashMap<char, usize> = HashMap::new();
+        let mut capped: HashSet<char> = HashSet::new();
+
+        for ColorInfo { index, chara, color } in row.iter() {
+            // a guess of another length may not have the position at all
+            match color {
+                Color::Green if guess.get(*index) != Some(chara) => return false,
+                Color::Yellow | Color::Grey if guess.get(*index) == Some(chara) => return false,
+                Color::Grey => {
+                    capped.insert(*chara);
+                }
+                _ => *revealed.entry(*chara).or_insert(0) += 1,
+            }
+        }
+
+        CHARS.iter().all(|key| {
+            let count = guess_frequency[key];
+            let revealed = *revealed.get(key).unwrap_or(&0);
+            count >= revealed && (!capped.contains(key) || count == revealed)
+        })
+    })
+}
+
+/// Returns the number of restrictions in `index` that `guess` violates,
+/// counting each misplaced position and each wrong character count once
+pub fn restriction_violations(
+    guess: &str,
+    index: &InfoIndex,
+) -> usize {
+    let guess_frequency = str_frequency(guess, &CHARS);
+    let guess: Vec<char> = guess.chars().collect();
+
+    let mut violations = 0;
+    for key in CHARS.iter() {
+        let lookup = &index.lookup[key];
+
+        violations += lookup.correct.iter()
+            .filter(|correct| guess[**correct] != *key)
+            .count();
+
+        violations += lookup.incorrect.iter()
+            .filter(|incorrect| guess[**incorrect] == *key)
+            .count();
+
+        let count = guess_frequency[key];
+        if count < lookup.min || (lookup.max == lookup.min && count != lookup.min) {
+            violations += 1;
+        }
+    }
+
+    violations
+}
+
 
-/// Returns the list of color information for a given `guess` on the target `secret`
+/// Returns the list of color information for a given `guess` on the target `secret`.
+/// When their lengths differ, only the positions both have can be green
 pub fn set_colors(secret: &str, guess: &str) -> Vec<ColorInfo> {
     // create secret character frequency dict
     let mut secret_freq: HashMap<char, i32> = HashMap::new();
@@ -425,18 +768,18 @@ pub fn set_colors(secret: &str, guess: &str) -> Vec<ColorInfo> {
         }
     }
 
-    // fill output
+    // fill output, where a guess longer than the secret has no greens past its end
     let mut colors = vec![];
     for i in 0..guess.len() {
-        let secret_char = secret.chars().nth(i).unwrap();
+        let secret_char = secret.chars().nth(i);
         let guess_char = guess.chars().nth(i).unwrap();
 
-        if guess_char == secret_char {
+        if Some(guess_char) == secret_char {
             // add green
             colors.push(ColorInfo {
                 index: i,
                 chara: guess_char,
-                color: Color::GREEN,
+                color: Color::Green,
             });
         } else if secret_freq.contains_key(&guess_char) && secret_freq[&guess_char] > 0 {
             // add yellow
@@ -444,14 +787,14 @@ pub fn set_colors(secret: &str, guess: &str) -> Vec<ColorInfo> {
             colors.push(ColorInfo {
                 index: i,
                 chara: guess_char,
-                color: Color::YELLOW,
+                color: Color::Yellow,
             });
         } else {
             // add grey
             colors.push(ColorInfo {
                 index: i,
                 chara: guess_char,
-                color: Color::GREY,
+                color: Color::Grey,
             });
         }
     }
@@ -460,6 +803,219 @@ pub fn set_colors(secret: &str, guess: &str) -> Vec<ColorInfo> {
     colors
 }
 
+/// The colors a game gives back for one guess
+pub type Feedback = Vec<ColorInfo>;
+
+/// The reasons a game refuses a guess
+#[derive(Debug, PartialEq, Eq)]
+pub enum GuessError {
+    /// the referee refused the guess
+    Rejected(Rejection),
+    /// the game is already won or lost
+    GameOver,
+}
+
+impl fmt::Display for GuessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GuessError::Rejected(rejection) => write!(f, "{}", rejection),
+            GuessError::GameOver => write!(f, "the game is over"),
+        }
+    }
+}
+
+/// the number of guesses a game allows by default, as in the course game
+pub const MAX_GUESSES: usize = 6;
+
+/// The rules a `Game` is played by
+#[derive(Clone, Debug)]
+pub struct Rules {
+    /// the number of guesses allowed
+    pub max_guesses: usize,
+    /// whether guesses must be true equations, rather than any string of `CHARS`
+    pub equations_only: bool,
+    /// whether every guess must respect the hints revealed so far,
+    /// which has no effect when the feedback lies
+    pub hard_mode: bool,
+    /// the number of tiles given the wrong color in each row that is not
+    /// the secret, as in Fibble
+    pub lies: usize,
+    /// whether guesses may have any length from 7 to 15, whatever the length
+    /// of the secret, with greens only where both have the position
+    pub any_length: bool,
+}
+
+impl Default for Rules {
+    fn default() -> Rules {
+        Rules {
+            max_guesses: MAX_GUESSES,
+            equations_only: false,
+            hard_mode: false,
+            lies: 0,
+            any_length: false,
+        }
+    }
+}
+
+/// The `Game` struct owns a secret and the checked history of guesses made
+/// against it, and is what every front end plays through
+pub struct Game {
+    host: Host,
+    rules: Rules,
+    referee: Referee,
+    history: AllInfo,
+}
+
+/// How a game decides the feedback of a guess
+enum Host {
+    /// against a secret chosen up front
+    Fixed(String),
+    /// against whichever secrets keep the most of them possible, never
+    /// committing to one until only a single secret is left
+    Adversarial(Vec<String>),
+}
+
+impl Game {
+    /// Returns a new game with the given secret and rules, or why the secret
+    /// is not a true equation, whatever the rules allow guesses to be
+    pub fn new(secret: &str, rules: Rules) -> Result<Game, Rejection> {
+        check_secret(secret)?;
+        Ok(Game::hosted(Host::Fixed(secret.to_string()), secret.len(), rules))
+    }
+
+    /// Returns a new game of a difficulty (7-15) whose host does not commit to a
+    /// secret, giving each guess the feedback that leaves the most secrets possible
+    pub fn adversarial(difficulty: usize, rules: Rules) -> Game {
+        Game::hosted(Host::Adversarial(all_secrets(difficulty)), difficulty, rules)
+    }
+
+    fn hosted(host: Host, length: usize, rules: Rules) -> Game {
+        Game {
+            host,
+            referee: Referee::new(length, &rules),
+            rules,
+            history: vec![],
+        }
+    }
+
+    /// Returns the secret, which for an adversarial host is one of the secrets
+    /// still possible, and only settled once the game is won
+    pub fn secret(&self) -> &str {
+        match &self.host {
+            Host::Fixed(secret) => secret,
+            Host::Adversarial(secrets) => &secrets[0],
+        }
+    }
+
+    /// Returns the feedback of every guess made so far
+    pub fn history(&self) -> &AllInfo {
+        &self.history
+    }
+
+    /// Returns why a guess may not be made, if it may not
+    pub fn check(&self, guess: &str) -> Result<(), GuessError> {
+        if self.is_won() || self.is_lost() {
+            return Err(GuessError::GameOver);
+        }
+
+        // hard mode has no effect when the feedback lies
+        let hints = (self.rules.hard_mode && self.rules.lies == 0).then_some(&self.history);
+        self.referee.check(guess, hints).map_err(GuessError::Rejected)
+    }
+
+    /// Checks and makes a guess, returning its feedback
+    pub fn guess(&mut self, guess: &str) -> Result<Feedback, GuessError> {
+        self.check(guess)?;
+
+        let mut feedback = match &mut self.host {
+            Host::Fixed(secret) => set_colors(secret, guess),
+            Host::Adversarial(secrets) => {
+                keep_largest_bucket(secrets, guess);
+                set_colors(&secrets[0], guess)
+            }
+        };
+
+        if self.rules.lies > 0 && feedback.iter().any(|info| info.color != Color::Green) {
+            tell_lies(&mut feedback, self.rules.lies);
+        }
+
+        self.history.push(feedback.clone());
+
+        Ok(feedback)
+    }
+
+    /// Returns whether the last guess was the secret
+    pub fn is_won(&self) -> bool {
+        self.history.last()
+            .is_some_and(|row| row.len() == self.secret().len() && row.iter().all(|info| info.color == Color::Green))
+    }
+
+    /// Returns whether every allowed guess was made without finding the secret
+    pub fn is_lost(&self) -> bool {
+        !self.is_won() && self.remaining_guesses() == 0
+    }
+
+    pub fn max_guesses(&self) -> usize {
+        self.rules.max_guesses
+    }
+
+    /// Returns the number of tiles given the wrong color in each row that is not the secret
+    pub fn lies(&self) -> usize {
+        self.rules.lies
+    }
+
+    /// Returns the number of guesses left
+    pub fn remaining_guesses(&self) -> usize {
+        self.rules.max_guesses.saturating_sub(self.history.len())
+    }
+}
+
+
+/// Returns why a secret is not a true equation, if it is not
+pub fn check_secret(secret: &str) -> Result<(), Rejection> {
+    let rules = Rules { equations_only: true, ..Rules::default() };
+    Referee::new(secret.chars().count(), &rules).check(secret, None)
+}
+
+/// Gives `lies` random tiles of a row that is not all green another color,
+/// never making the row look all green
+fn tell_lies(feedback: &mut Feedback, lies: usize) {
+    let mut rng = rand::thread_rng();
+    let truth: Vec<Color> = feedback.iter().map(|info| info.color).collect();
+
+    loop {
+        for position in rand::seq::index::sample(&mut rng, feedback.len(), lies.min(feedback.len())) {
+            let others: Vec<Color> = [Color::Green, Color::Yellow, Color::Grey].into_iter()
+                .filter(|color| *color != truth[position])
+                .collect();
+            feedback[position].color = *others.choose(&mut rng).unwrap();
+        }
+
+        if feedback.iter().any(|info| info.color != Color::Green) {
+            return;
+        }
+
+        for (info, color) in feedback.iter_mut().zip(truth.iter()) {
+            info.color = *color;
+        }
+    }
+}
+
+/// Keeps the secrets giving the most common pattern for a guess, preferring
+/// the lowest pattern id on ties, which favours greys
+fn keep_largest_bucket(secrets: &mut Vec<String>, guess: &str) {
+    let mut sizes: HashMap<u32, usize> = HashMap::new();
+    for secret in secrets.iter() {
+        *sizes.entry(pattern(secret.as_bytes(), guess.as_bytes())).or_insert(0) += 1;
+    }
+
+    let id = sizes.into_iter()
+        .max_by_key(|(id, size)| (*size, std::cmp::Reverse(*id)))
+        .map_or(0, |(id, _)| id);
+
+    secrets.retain(|secret| pattern(secret.as_bytes(), guess.as_bytes()) == id);
+}
+
 
 /// Returns a random valid focdle expression
 fn random_expression() -> String {
@@ -480,7 +1036,7 @@ fn random_expression() -> String {
 
 /// Returns a valid focdle secret of a given difficulty (7-15)
 pub fn create_secret(difficulty: usize) -> String {
-    if difficulty < 7 || difficulty > 15 {
+    if !(7..=15).contains(&difficulty) {
         panic!("cannot generate a secret of difficulty not between 7 and 15");
     }
 
@@ -503,3 +1059,372 @@ pub fn create_secret(difficulty: usize) -> String {
 
 
 
+
+/// Returns the number of decimal digits in a positive number
+pub fn digits(number: i32) -> usize {
+    let mut number = number;
+    let mut count = 1;
+    while number >= 10 {
+        number /= 10;
+        count += 1;
+    }
+
+    count
+}
+
+/// Returns every valid focdle secret of a given difficulty (7-15), that is,
+/// every equation `create_secret` could possibly generate
+pub fn all_secrets(difficulty: usize) -> Vec<String> {
+    secrets_where(difficulty..=difficulty, |_| true)
+}
+
+/// Returns every valid focdle secret of a given difficulty that would have
+/// produced exactly the colors in `info`
+pub fn consistent_secrets(difficulty: usize, info: &AllInfo) -> Vec<String> {
+    secrets_where(difficulty..=difficulty, agrees_with(info))
+}
+
+/// Returns every valid focdle secret of any length (7-15) that would have
+/// produced exactly the colors in `info`, whatever the lengths of the guesses
+pub fn consistent_secrets_of_any_length(info: &AllInfo) -> Vec<String> {
+    secrets_where(7..=15, agrees_with(info))
+}
+
+/// Returns whether a secret would have produced exactly the colors in `info`
+fn agrees_with(info: &AllInfo) -> impl Fn(&str) -> bool {
+    let observations: Vec<(Vec<u8>, u32)> = info.iter()
+        .map(|colors| (
+            colors.iter().map(|c| c.chara as u8).collect(),
+            pattern_id(colors.iter().map(|info| info.color)),
+        ))
+        .collect();
+
+    move |secret| {
+        observations.iter()
+            .all(|(guess, id)| pattern(secret.as_bytes(), guess) == *id)
+    }
+}
+
+/// Returns every valid focdle secret with a length in `lengths` that is kept by
+/// the filter, ordered by expression length and then by numbers and operators
+fn secrets_where(lengths: RangeInclusive<usize>, keep: impl Fn(&str) -> bool) -> Vec<String> {
+    // one list per expression length, as they are enumerated together
+    let mut secrets: [Vec<String>; 4] = Default::default();
+    let mut secret: Vec<u8> = Vec::with_capacity(*lengths.end());
+
+    for_each_secret(lengths, |expression_length, [num1, num2, num3], [op1, op2], outcome| {
+        // written in place, so that only the kept secrets are allocated
+        secret.clear();
+        push_number(&mut secret, num1);
+        secret.push(OPERATORS[op1] as u8);
+        push_number(&mut secret, num2);
+        secret.push(OPERATORS[op2] as u8);
+        push_number(&mut secret, num3);
+        secret.push(b'=');
+        push_number(&mut secret, outcome);
+
+        let text = std::str::from_utf8(&secret).unwrap();
+        if keep(text) {
+            secrets[expression_length - 5].push(text.to_string());
+        }
+    });
+
+    secrets.concat()
+}
+
+/// Appends the decimal digits of a positive number
+fn push_number(text: &mut Vec<u8>, number: i32) {
+    if number >= 10 {
+        push_number(text, number / 10);
+    }
+
+    text.push(b'0' + (number % 10) as u8);
+}
+
+/// Returns the number of valid focdle secrets of a given difficulty, without building them
+pub fn count_secrets(difficulty: usize) -> usize {
+    equality_counts(difficulty).iter().sum()
+}
+
+/// Returns the number of valid focdle secrets of a given difficulty with the
+/// equality sign at each position, counted on first use and then shared
+pub fn equality_counts(difficulty: usize) -> Vec<usize> {
+    let mut counts = EQUALITY_COUNTS.lock().unwrap();
+    counts.entry(difficulty)
+        .or_insert_with(|| {
+            let mut counts = vec![0; difficulty];
+            for_each_secret(difficulty..=difficulty, |expression_length, _, _, _| counts[expression_length] += 1);
+            counts
+        })
+        .clone()
+}
+
+/// Calls `visit` with the expression length, numbers, operator indices and
+/// result of every valid focdle secret with a length in `lengths`
+fn for_each_secret(lengths: RangeInclusive<usize>, mut visit: impl FnMut(usize, [i32; 3], [usize; 2], i32)) {
+    // expressions take between 5 and 8 characters, then the equality sign and a digit at least
+    let longest = min(8, lengths.end().saturating_sub(2));
+    if longest < 5 {
+        return;
+    }
+
+    for_each_expression(5..=longest, |expression_length, nums, ops, outcome| {
+        if lengths.contains(&(expression_length + 1 + digits(outcome))) {
+            visit(expression_length, nums, ops, outcome);
+        }
+    });
+}
+
+/// Calls `visit` with the length, numbers, operator indices and result of
+/// every expression with a length in `lengths` that has a positive result,
+/// going through the numbers once whatever the number of lengths
+pub fn for_each_expression(lengths: RangeInclusive<usize>, mut visit: impl FnMut(usize, [i32; 3], [usize; 2], i32)) {
+    for num1 in 1..100 {
+        for num2 in 1..100 {
+            for num3 in 1..100 {
+                // plus the two operators
+                let expression_length = digits(num1) + digits(num2) + digits(num3) + 2;
+                if !lengths.contains(&expression_length) {
+                    continue;
+                }
+
+                for op1 in 0..OPERATORS.len() {
+                    for op2 in 0..OPERATORS.len() {
+                        let outcome = eval_parts([num1, num2, num3], [op1 as i32, op2 as i32]);
+                        if let Some(outcome) = outcome.filter(|outcome| *outcome > 0) {
+                            visit(expression_length, [num1, num2, num3], [op1, op2], outcome);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pruning_keeps_every_prefix_of_a_secret() {
+        for difficulty in [7, 9, 12, 15] {
+            for secret in all_secrets(difficulty).iter().step_by(53) {
+                let expression_length = secret.find('=').unwrap();
+                let result_length = difficulty - expression_length - 1;
+                let chars: Vec<char> = secret.chars().collect();
+
+                for end in 0..=expression_length {
+                    assert!(
+                        result_may_fit(&chars[..end], expression_length, result_length),
+                        "{} pruned at {}", secret, end,
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pruning_drops_results_out_of_reach() {
+        // 1+1+ leaves one digit, so the result is at most 1+1+9 = 11
+        let prefix: Vec<char> = "1+1+".chars().collect();
+        assert!(result_may_fit(&prefix, 5, 2));
+        assert!(!result_may_fit(&prefix, 5, 3));
+    }
+
+    #[test]
+    fn secrets_are_valid_and_ordered_by_expression_length() {
+        for difficulty in [7, 8, 9] {
+            let secrets = all_secrets(difficulty);
+            assert_eq!(secrets.len(), count_secrets(difficulty));
+            assert_eq!(secrets.iter().collect::<HashSet<_>>().len(), secrets.len());
+
+            let mut last_expression_length = 0;
+            for secret in secrets.iter() {
+                let (expression, result) = secret.split_once('=').unwrap();
+                assert_eq!(secret.len(), difficulty);
+                assert_eq!(fast_eval(expression), Some(result.parse().unwrap()), "{}", secret);
+                assert!(expression.len() >= last_expression_length, "{} out of order", secret);
+                last_expression_length = expression.len();
+            }
+        }
+    }
+
+    #[test]
+    fn consistent_secrets_keep_the_secret() {
+        let secret = "12+3*4=24";
+        let info: AllInfo = ["1+2*34=69", "98-7*6=56"].iter()
+            .map(|guess| set_colors(secret, guess))
+            .collect();
+
+        let consistent = consistent_secrets(secret.len(), &info);
+        assert!(consistent.contains(&secret.to_string()));
+        for candidate in consistent.iter() {
+            for row in info.iter() {
+                let guess: String = row.iter().map(|info| info.chara).collect();
+                assert_eq!(&set_colors(candidate, &guess), row);
+            }
+        }
+    }
+
+    #[test]
+    fn equality_counts_match_the_secrets() {
+        for difficulty in [7, 9] {
+            let mut expected = vec![0; difficulty];
+            for secret in all_secrets(difficulty).iter() {
+                expected[secret.find('=').unwrap()] += 1;
+            }
+
+            assert_eq!(equality_counts(difficulty), expected);
+        }
+    }
+
+    #[test]
+    fn new_index_allows_only_valid_ends() {
+        let index = InfoIndex::new(8);
+
+        assert!(index.allowed[0].iter().all(|c| NUMS.contains(c) && *c != '0'));
+        assert!(index.allowed[7].iter().all(|c| NUMS.contains(c)));
+        for position in (0..5).chain(7..8) {
+            assert!(!index.allowed[position].contains(&'='), "= allowed at {}", position);
+        }
+    }
+
+    #[test]
+    fn observed_hints_keep_the_secret_allowed() {
+        for secret in all_secrets(9).iter().step_by(97) {
+            let info: AllInfo = ["1+2*34=69", "98-7*6=56", "7%4+50=53"].iter()
+                .map(|guess| set_colors(secret, guess))
+                .collect();
+            let index = InfoIndex::build(9, &info);
+
+            for (position, chara) in secret.chars().enumerate() {
+                assert!(index.allowed[position].contains(&chara), "{} ruled out at {}", secret, position);
+            }
+
+            for key in CHARS.iter() {
+                let count = secret.chars().filter(|c| c == key).count();
+                let lookup = &index.lookup[key];
+                assert!(lookup.min <= count && count <= lookup.max, "{} count of {} out of bounds", secret, key);
+            }
+        }
+    }
+
+    #[test]
+    fn observing_the_secret_fixes_every_position() {
+        let secret = "12+3*4=24";
+        let mut index = InfoIndex::new(secret.len());
+        index.observe(&set_colors(secret, secret));
+
+        for (position, chara) in secret.chars().enumerate() {
+            assert_eq!(index.allowed[position], HashSet::from([chara]));
+        }
+    }
+
+    #[test]
+    fn two_operators_found_rule_out_the_others() {
+        let mut index = InfoIndex::new(9);
+        index.observe(&set_colors("12+3*4=24", "3*4+12=24"));
+
+        assert_eq!(index.lookup[&'+'].max, 1);
+        assert_eq!(index.lookup[&'*'].max, 1);
+        assert_eq!(index.lookup[&'-'].max, 0);
+        assert_eq!(index.lookup[&'%'].max, 0);
+    }
+
+    #[test]
+    fn adversary_keeps_the_largest_bucket() {
+        let mut secrets: Vec<String> = ["1+1+1=3", "1+1+2=4", "2+2+2=6", "9*9-9=72"].iter()
+            .map(|secret| secret.to_string())
+            .collect();
+        let all = secrets.clone();
+        keep_largest_bucket(&mut secrets, "1+1+1=3");
+
+        // every other secret is its own bucket, so the tie goes to the greyest
+        let greyest = all.iter()
+            .min_by_key(|secret| pattern(secret.as_bytes(), b"1+1+1=3"))
+            .unwrap();
+        assert_eq!(secrets, vec![greyest.clone()]);
+    }
+
+    #[test]
+    fn adversary_buckets_agree_with_the_feedback() {
+        let mut secrets = all_secrets(7);
+        let mut sizes: HashMap<u32, usize> = HashMap::new();
+        for secret in secrets.iter() {
+            *sizes.entry(pattern(secret.as_bytes(), b"1+2*3=7")).or_insert(0) += 1;
+        }
+        keep_largest_bucket(&mut secrets, "1+2*3=7");
+
+        assert_eq!(secrets.len(), sizes.into_values().max().unwrap());
+        let colors = set_colors(&secrets[0], "1+2*3=7");
+        for secret in secrets.iter() {
+            assert_eq!(set_colors(secret, "1+2*3=7"), colors);
+        }
+    }
+
+    #[test]
+    fn contradicting_rows_do_not_overflow_the_counts() {
+        // as a lying row could claim, more digits than the secret has room for
+        let row: Vec<ColorInfo> = (0..7)
+            .map(|index| ColorInfo { index, chara: '1', color: Color::Green })
+            .collect();
+        let index = InfoIndex::build(7, &vec![row]);
+
+        assert_eq!(index.lookup[&'2'].max, 0);
+    }
+
+    #[test]
+    fn games_give_the_colors_of_each_guess() {
+        let mut game = Game::new("9*8-7=65", Rules::default()).unwrap();
+        for guess in ["13+45=58", "2*3+4=10"] {
+            assert_eq!(game.guess(guess), Ok(set_colors("9*8-7=65", guess)));
+        }
+
+        assert_eq!(game.history(), &vec![set_colors("9*8-7=65", "13+45=58"), set_colors("9*8-7=65", "2*3+4=10")]);
+        assert!(!game.is_won());
+    }
+
+    #[test]
+    fn games_are_over_once_won() {
+        let mut game = Game::new("9*8-7=65", Rules::default()).unwrap();
+        game.guess("9*8-7=65").unwrap();
+
+        assert!(game.is_won());
+        assert!(!game.is_lost());
+        assert_eq!(game.guess("13+45=58"), Err(GuessError::GameOver));
+        assert_eq!(game.history().len(), 1);
+    }
+
+    #[test]
+    fn games_are_over_after_the_last_guess() {
+        let mut game = Game::new("9*8-7=65", Rules { max_guesses: 3, ..Rules::default() }).unwrap();
+        for remaining in (0..3).rev() {
+            game.guess("13+45=58").unwrap();
+            assert_eq!(game.remaining_guesses(), remaining);
+        }
+
+        assert!(game.is_lost());
+        assert_eq!(game.guess("9*8-7=65"), Err(GuessError::GameOver));
+        assert_eq!(game.remaining_guesses(), 0);
+    }
+
+    #[test]
+    fn longer_guesses_are_not_a_win() {
+        let mut game = Game::new("9*8-7=65", Rules { any_length: true, ..Rules::default() }).unwrap();
+
+        // every position of the secret is green, but the guess has more of them
+        let colors = game.guess("9*8-7=650").unwrap();
+        assert!(colors[..8].iter().all(|info| info.color == Color::Green));
+        assert!(!game.is_won());
+
+        game.guess("9*8-7=65").unwrap();
+        assert!(game.is_won());
+    }
+
+    #[test]
+    fn games_refuse_secrets_that_are_not_true_equations() {
+        assert_eq!(Game::new("12+34=46", Rules::default()).err(), Some(Rejection::MalformedExpression { position: 5 }));
+        assert_eq!(Game::new("9*8-7=66", Rules::default()).err(), Some(Rejection::WrongResult { value: Some(65), result: 66 }));
+        assert!(Game::new("9*8-7=65", Rules::default()).is_ok());
+    }
+}
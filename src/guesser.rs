@@ -1,9 +1,14 @@
-use std::cmp::{min};
 use std::collections::{HashMap, HashSet};
 use lazy_static::lazy_static;
 use rand::{Rng};
-use crate::game::{AllInfo, CHARS, fast_eval, InfoIndex, InfoLookup, OPERATORS, passes_restrictions};
+use rand::seq::SliceRandom;
+use crate::budget::Budget;
+use crate::config::{EqualityMode, FailPolicy, FailState, GuesserConfig, SearchOrder};
+use crate::equations::EquationIndex;
+use crate::game::{CHARS, equality_counts, fast_eval, InfoIndex, InfoLookup, OPERATORS, passes_restrictions, restriction_violations, result_may_fit};
 use crate::game::NUMS;
+use crate::lookahead;
+use crate::matrix::{expected_bucket, FeedbackMatrix};
 
 /// the initial optimal guesses
 static OPTIMAL_GUESSES: [&str; 9] = [
@@ -21,10 +26,8 @@ static OPTIMAL_GUESSES: [&str; 9] = [
 /// returns the loaded json frequency, included at compile-time for performance
 fn load_frequency() -> serde_json::Value {
     let text = include_str!("resources/freq2.json");
-    let value = serde_json::from_str(text)
-        .expect("Cannot parse the content in 'freq2.json'!");
-
-    value
+    serde_json::from_str(text)
+        .expect("Cannot parse the content in 'freq2.json'!")
 }
 
 lazy_static! {
@@ -33,30 +36,46 @@ lazy_static! {
 
 /// The `Guesser` struct provides an interface to the searching algorithm
 pub struct Guesser {
-    searches: i32,
+    /// number of search nodes visited by the guess, as a guesser makes a single one
+    searches: usize,
     index: InfoIndex,
     difficulty: usize,
     attempt: usize,
+    config: GuesserConfig,
+    /// the secrets consistent with the info, only gathered for policies that need them
+    candidates: Vec<String>,
 }
 
 
 impl Guesser {
-    /// Returns a new Guesser given a difficulty and current info
-    pub fn new(difficulty: usize, all_info: &AllInfo) -> Guesser {
-        let index = InfoIndex::build(difficulty, all_info);
+    /// Returns a new Guesser given the index of the current info, the number
+    /// of guesses made so far, the candidates gathered for `needs_candidates`
+    /// and tuning config
+    pub fn new(index: InfoIndex, attempt: usize, candidates: Vec<String>, config: GuesserConfig) -> Guesser {
         Guesser {
             searches: 0,
+            difficulty: index.difficulty,
             index,
-            difficulty,
-            attempt: all_info.len(),
+            attempt,
+            config,
+            candidates,
         }
     }
 
+    /// Returns whether a guesser with this config uses the secrets consistent
+    /// with the info. The weighted equality mode weights the positions by
+    /// them until the equality is green. Hard mode falls back to them when
+    /// the budget runs out before the search finds a guess respecting the hints
+    pub fn needs_candidates(config: &GuesserConfig, index: &InfoIndex) -> bool {
+        let weighting = matches!(config.equality, EqualityMode::Weighted) && index.lookup[&'='].correct.len() != 1;
+        matches!(config.fail_policy, FailPolicy::Reduction) || config.lookahead.is_some() || config.hard_mode || weighting
+    }
+
     /// Returns a sorted character array given a mutable choice array
     /// The first entry is the highest rated character
     fn choice_sort(
         &self,
-        choices: &mut Vec<char>,
+        choices: &mut [char],
         position: usize,
         frequency: &HashMap<char, usize>,
     ) {
@@ -65,36 +84,27 @@ impl Guesser {
         let lookup = &FREQUENCY[&self.difficulty.to_string()][&position.to_string()];
         choices.sort_by_cached_key(|c| {
             let value = if frequency.is_empty() {
-                0.1 * rng.gen::<f64>() - lookup[&c.to_string()].as_f64().unwrap()
+                self.config.noise * rng.gen::<f64>() - lookup[&c.to_string()].as_f64().unwrap()
             } else {
-                0.1 * rng.gen::<f64>() - 5.0 * lookup[&c.to_string()].as_f64().unwrap() + frequency[&c] as f64
+                self.config.noise * rng.gen::<f64>()
+                    - self.config.frequency_weight * lookup[&c.to_string()].as_f64().unwrap()
+                    + frequency[c] as f64
             };
 
 
-            return (value * 1000000.0) as i32;
+            (value * 1000000.0) as i32
         });
     }
 
     /// Returns the initial guess from the guesser
     fn initial_guess(&self) -> String {
-        let mut nums: Vec<char> = NUMS.to_vec();
-
-        // reasons
-        if self.difficulty >= 8 {
-            nums.push('%');
-        }
-        if self.difficulty >= 13 {
-            nums.extend(OPERATORS.iter());
-        }
-        if self.difficulty >= 14 {
-            nums.push('9');
-        }
+        let mut nums: Vec<char> = self.config.initial_characters(self.difficulty);
 
         let mut template: Vec<char> = OPTIMAL_GUESSES[self.difficulty - 7].chars().collect();
 
         let mut frequency = HashMap::new();
         for key in CHARS.iter() {
-            frequency.insert(key.clone(), 0);
+            frequency.insert(*key, 0);
         }
 
         // replace stuff with random
@@ -115,88 +125,109 @@ impl Guesser {
         template.iter().collect()
     }
 
-    /// Return the length of the result by deducing the equality operator position
-    fn deduce_equality(&mut self) -> usize {
-        let result_length: usize;
-
+    /// Returns the possible equality operator positions, the most likely first
+    fn deduce_equality(&mut self) -> Vec<usize> {
         let equality = self.index.lookup.get_mut(&'=').unwrap();
-        if equality.correct.len() != 1 {
-            let position = min(self.difficulty - 2, 8);
+        if equality.correct.len() == 1 {
+            return vec![*equality.correct.iter().next().unwrap()];
+        }
 
-            equality.correct.clear();
-            equality.correct.insert(position);
+        let assumed = self.config.equality_position(self.difficulty);
+        match self.config.equality {
+            EqualityMode::Assume => {
+                equality.correct.clear();
+                equality.correct.insert(assumed);
+                self.index.propagate();
 
-            result_length = self.difficulty - position - 1;
-        } else {
-            result_length = self.difficulty - equality.correct.iter().next().unwrap() - 1;
-        }
+                vec![assumed]
+            }
+            EqualityMode::Weighted => {
+                // weight each position by the number of consistent secrets placing it there.
+                // When no secret fits the hints, the positions the hints still allow are
+                // weighted by the number of secrets placing it there instead
+                let mut counts: HashMap<usize, usize> = HashMap::new();
+                if self.candidates.is_empty() {
+                    for (position, count) in equality_counts(self.difficulty).into_iter().enumerate() {
+                        if count > 0 && self.index.allowed[position].contains(&'=') {
+                            counts.insert(position, count);
+                        }
+                    }
+                } else {
+                    for candidate in self.candidates.iter() {
+                        *counts.entry(candidate.find('=').unwrap()).or_insert(0) += 1;
+                    }
+                }
+
+                let mut positions: Vec<(usize, usize)> = counts.into_iter().collect();
+                positions.sort_by_key(|(position, count)| (usize::MAX - count, *position));
 
-        result_length
+                if positions.is_empty() {
+                    return vec![assumed];
+                }
+
+                positions.into_iter().map(|(position, _)| position).collect()
+            }
+        }
     }
 
     /// Returns if the guesser should attempt to fall at this stage
     fn should_fail(&self) -> bool {
         // count greens
         let mut correct = 0;
-        let mut correct_operators = vec![];
+        let mut present = vec![];
+        let mut operators = vec![];
         for (key, value) in self.index.lookup.iter() {
             correct += value.correct.len();
-            if OPERATORS.contains(key) && value.min > 0 {
-                correct_operators.push(key.clone());
+            if value.min > 0 {
+                present.push(*key);
+                if OPERATORS.contains(key) {
+                    operators.push(*key);
+                }
             }
         }
 
-        let diff = self.difficulty;
-        match self.difficulty {
-            7 => {
-                return false;
-            }
-            8 => {
-                return self.attempt == 1;
-            }
-            9 => {
-                if self.attempt == 1 {
-                    return true;
-                }
-
-                if self.index.lookup[&'%'].min > 0 && (diff - correct <= 4) {
-                    if self.attempt == 2 {
-                        return true;
-                    }
-                }
-            }
-            10 => {
-                if self.attempt == 1 {
-                    return true;
-                }
+        // the rules were calibrated with the assumed `=` counted as green,
+        // so it is counted the same way when the positions are weighted
+        if self.index.lookup[&'='].correct.is_empty() {
+            correct += 1;
+        }
 
-                if self.attempt == 3 && correct_operators.contains(&'%')
-                    && correct_operators.len() == 1
-                    && 1 < (diff - correct)
-                    && (diff - correct) <= 3 {
-                    return true;
-                }
-            }
-            _ => {
-                if self.attempt == 1 {
-                    return true;
-                }
+        self.config.fail_policy.matches(&FailState {
+            difficulty: self.difficulty,
+            attempt: self.attempt,
+            unknown: self.difficulty.saturating_sub(correct),
+            present: &present,
+            operators: &operators,
+        })
+    }
 
-                if self.attempt == 2 && (diff - correct) <= 2 {
-                    return true;
-                }
+    /// Returns the guess that is expected to leave fewer candidates,
+    /// preferring the candidate guess on ties since it may also win, or
+    /// when the budget runs out before both are scored
+    fn least_remaining(&self, explore: String, candidate: String, budget: &mut Budget) -> String {
+        if explore == candidate {
+            return candidate;
+        }
 
+        let guesses = [explore.clone(), candidate.clone()];
+        let matrix = FeedbackMatrix::for_turn(self.difficulty, &guesses, &self.candidates, self.config.cache_directory.as_deref());
+        let explore_remaining = self.expected_remaining(&explore, &matrix, budget);
+        let candidate_remaining = self.expected_remaining(&candidate, &matrix, budget);
 
-                if self.attempt == 3 && correct_operators.contains(&'%')
-                    && correct_operators.len() == 1
-                    && 1 < (diff - correct)
-                    && (diff - correct) <= 3 {
-                    return true;
-                }
-            }
+        match (explore_remaining, candidate_remaining) {
+            (Some(explore_remaining), Some(candidate_remaining)) if explore_remaining < candidate_remaining => explore,
+            _ => candidate,
         }
+    }
+
+    /// Returns the expected number of candidates left after a guess, reading
+    /// the feedback from the matrix of the turn, or None if the budget runs out first
+    fn expected_remaining(&self, guess: &str, matrix: &FeedbackMatrix, budget: &mut Budget) -> Option<f64> {
+        let feedback: Option<Vec<u32>> = self.candidates.iter()
+            .map(|secret| budget.visit().then(|| matrix.feedback(guess, secret)))
+            .collect();
 
-        false
+        Some(expected_bucket(feedback?))
     }
 
     /// Returns some helpful lookup caching data structures
@@ -231,7 +262,7 @@ impl Guesser {
 
     fn fail_filters(
         &self,
-        position: usize, output: &Vec<char>,
+        position: usize, output: &[char],
         ops_left: i32, ops_valid: usize,
     ) -> HashSet<char> {
         let mut filter = HashSet::new();
@@ -266,7 +297,7 @@ impl Guesser {
 
     fn normal_filters(
         &self,
-        position: usize, output: &Vec<char>,
+        position: usize, output: &[char],
         ops_left: i32, ops_valid: usize,
         chars_remain: usize,
     ) -> HashSet<char> {
@@ -314,45 +345,226 @@ impl Guesser {
 
 
     /// Returns the best guess the guesser could possibly make.
+    /// The search stops at the configured deadline or node limit,
+    /// returning the best guess found so far
     pub fn create_guess(&mut self) -> String {
         // initial guess
         if self.attempt == 0 {
             return self.initial_guess();
         }
 
-        let result_length = self.deduce_equality();
-        let expression_length = self.difficulty - result_length - 1;
+        let mut budget = Budget::new(self.config.time_limit, self.config.node_limit);
+        let greedy = self.greedy_guess(&mut budget);
+        self.searches += budget.nodes();
+
+        match &self.config.lookahead {
+            Some(config) if !self.candidates.is_empty() => {
+                let mut budget = Budget::new(config.time_limit, config.node_limit);
+                let guess = lookahead::best_guess(
+                    self.difficulty,
+                    &self.candidates,
+                    &[greedy],
+                    config,
+                    self.config.cache_directory.as_deref(),
+                    &mut budget,
+                );
 
-        let fail = self.should_fail();
-        let (valid, valid_positions, valid_operators) = self.create_guess_variables();
+                self.searches += budget.nodes();
+                guess
+            }
+            _ => greedy,
+        }
+    }
 
-        return if fail {
-            self.backtrack_fail(
-                valid,
-                valid_positions,
-                valid_operators,
-                expression_length,
-                result_length,
-            )
+    /// Returns the number of search nodes visited by `create_guess`
+    pub fn searches(&self) -> usize {
+        self.searches
+    }
+
+    /// Returns the first guess found by the search, in frequency order
+    fn greedy_guess(&mut self, budget: &mut Budget) -> String {
+        if self.config.hard_mode {
+            return self.hard_guess(budget);
+        }
+
+        let positions = self.deduce_equality();
+
+        if let FailPolicy::Reduction = self.config.fail_policy {
+            let candidate = self.candidate_guess(&positions, budget);
+
+            // a single candidate left is the secret, so there is nothing to explore
+            if self.candidates.len() <= 1 {
+                return candidate;
+            }
+
+            let explore = self.exploratory_guess(budget);
+            return self.least_remaining(explore, candidate, budget);
+        }
+
+        if self.should_fail() {
+            self.exploratory_guess(budget)
         } else {
-            self.backtrack(
-                valid,
-                valid_positions,
-                valid_operators,
-                expression_length,
-                result_length,
-            )
-        };
+            self.candidate_guess(&positions, budget)
+        }
     }
 
-    /// Returns the best guess by failing all the valid positions
+    /// Returns a guess respecting every hint, trying the deduced equality
+    /// positions first and then every other position the hints allow. When
+    /// the budget runs out first, a guess breaking a hint would be refused,
+    /// so one of the candidates is guessed instead
+    fn hard_guess(&mut self, budget: &mut Budget) -> String {
+        let hints = self.index.clone();
+        let deduced = self.deduce_equality();
+
+        let mut positions = deduced.clone();
+        positions.extend((0..self.difficulty)
+            .filter(|position| hints.allowed[*position].contains(&'=') && !deduced.contains(position)));
+
+        for (i, expression_length) in positions.iter().enumerate() {
+            // the assumed position is only a guess, the others are searched on the hints alone
+            if i == deduced.len() {
+                self.index = hints.clone();
+            }
+
+            let result_length = self.difficulty - expression_length - 1;
+            match self.search(*expression_length, result_length, budget) {
+                Some(guess) if passes_restrictions(&guess, &hints) => return guess,
+                _ => continue,
+            }
+        }
+
+        if let Some(candidate) = self.candidates.first() {
+            return candidate.clone();
+        }
+
+        // only reached when no secret fits the hints
+        self.exploratory_guess(budget)
+    }
+
+    /// Returns a guess that avoids every known position
+    fn exploratory_guess(&self, budget: &mut Budget) -> String {
+        let (valid, valid_positions, valid_operators) = self.create_guess_variables();
+        self.backtrack_fail(
+            valid,
+            valid_positions,
+            valid_operators,
+            budget,
+        )
+    }
+
+    /// Returns a guess that could be the secret, trying each equality position
+    /// in turn, or the equation breaking the fewest restrictions if the budget
+    /// runs out, or an exploratory guess if no equation was reached at all
+    fn candidate_guess(&self, positions: &[usize], budget: &mut Budget) -> String {
+        for expression_length in positions.iter() {
+            let result_length = self.difficulty - expression_length - 1;
+            if let Some(guess) = self.search(*expression_length, result_length, budget) {
+                return guess;
+            }
+        }
+
+        self.exploratory_guess(budget)
+    }
+
+    /// Returns a guess with the equality operator after `expression_length`
+    /// characters, in the configured search order
+    fn search(&self, expression_length: usize, result_length: usize, budget: &mut Budget) -> Option<String> {
+        match self.config.search {
+            SearchOrder::Backtrack => {
+                let (valid, valid_positions, valid_operators) = self.create_guess_variables();
+                self.backtrack(
+                    valid,
+                    valid_positions,
+                    valid_operators,
+                    expression_length,
+                    result_length,
+                    budget,
+                )
+            }
+            SearchOrder::Results => self.result_first(expression_length, result_length, budget),
+        }
+    }
+
+    /// Returns if the digits of a result, placed after the equality sign,
+    /// agree with the known positions and counts
+    fn result_fits(&self, result: &[char], expression_length: usize) -> bool {
+        let offset = expression_length + 1;
+
+        let mut counts: HashMap<char, usize> = HashMap::new();
+        for (i, c) in result.iter().enumerate() {
+            let lookup = &self.index.lookup[c];
+            if !self.index.allowed[offset + i].contains(c) {
+                return false;
+            }
+
+            let count = counts.entry(*c).or_insert(0);
+            *count += 1;
+            if *count > lookup.max {
+                return false;
+            }
+        }
+
+        for (key, lookup) in self.index.lookup.iter() {
+            for correct in lookup.correct.iter() {
+                if *correct >= offset && result[*correct - offset] != *key {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Returns the first guess passing the restrictions, searching the results
+    /// that fit the hints first, and then the expressions that evaluate to them.
+    /// Returns the equation breaking the fewest restrictions if the budget runs
+    /// out, and None when every expression was tried
+    fn result_first(&self, expression_length: usize, result_length: usize, budget: &mut Budget) -> Option<String> {
+        let equations = EquationIndex::for_length(expression_length);
+
+        let mut results = equations.results(result_length);
+        results.shuffle(&mut rand::thread_rng());
+
+        // the equation breaking the fewest restrictions, in case the budget runs out
+        let mut best: Option<(usize, String)> = None;
+
+        for result in results {
+            let result = result.to_string();
+            let result_chars: Vec<char> = result.chars().collect();
+            if !self.result_fits(&result_chars, expression_length) {
+                continue;
+            }
+
+            for expression in equations.expressions(result.parse().unwrap()) {
+                if !budget.visit() {
+                    return best.map(|(_, guess)| guess);
+                }
+
+                let guess = format!("{}={}", expression, result);
+                if passes_restrictions(&guess, &self.index) {
+                    return Some(guess);
+                }
+
+                let violations = restriction_violations(&guess, &self.index);
+                if best.as_ref().is_none_or(|(fewest, _)| violations < *fewest) {
+                    best = Some((violations, guess));
+                }
+            }
+        }
+
+        // every expression was tried, so no equation with this length fits
+        None
+    }
+
+    /// Returns the best guess by failing all the valid positions. Every
+    /// position is placed once and never revisited, so the guess is always
+    /// finished, visiting one node of the budget per position
     fn backtrack_fail(
         &self,
         valid: Vec<(char, &InfoLookup)>,
         valid_positions: HashMap<usize, char>,
         valid_operators: usize,
-        expression_length: usize,
-        result_length: usize,
+        budget: &mut Budget,
     ) -> String {
 
         // create output array
@@ -373,14 +585,14 @@ impl Guesser {
             let mut ops_left: i32 = 2;
             // state variable
             for key in CHARS.iter() {
-                output_frequency.insert(key.clone(), 0);
+                output_frequency.insert(*key, 0);
             }
 
             // add top entries --- the current partial guess
             let mut output = vec![];
             for choices in stack.iter() {
-                if choices.len() > 0 {
-                    let item = choices.last().unwrap().clone();
+                if !choices.is_empty() {
+                    let item = *choices.last().unwrap();
 
                     if OPERATORS.contains(&item) {
                         ops_left -= 1;
@@ -401,6 +613,9 @@ impl Guesser {
                 return output.iter().collect();
             }
 
+            // counted only, the few positions left are placed even once it is spent
+            budget.visit();
+
             // create filter
             let mut filter = self.fail_filters(
                 position,
@@ -412,7 +627,7 @@ impl Guesser {
             // avoid using correct positions
             if valid_positions.contains_key(&position) {
                 filter.insert(
-                    valid_positions.get(&position).unwrap().clone()
+                    *valid_positions.get(&position).unwrap()
                 );
             }
 
@@ -423,7 +638,7 @@ impl Guesser {
 
             // compute next position choices
             // the target empty vec
-            let mut choices = &mut stack[position];
+            let choices = &mut stack[position];
             for (key, lookup) in valid.iter() {
                 // enact filter
                 if filter.contains(key) {
@@ -436,18 +651,18 @@ impl Guesser {
                 }
 
                 // don't add if we exceeded character maximum
-                let char_freq = output_frequency.get(&key).unwrap().clone();
+                let char_freq = *output_frequency.get(key).unwrap();
                 let too_much = (lookup.max == lookup.min) && (lookup.min <= char_freq);
                 if too_much {
                     continue;
                 }
 
-                choices.push(key.clone());
+                choices.push(*key);
             }
 
 
             // ignore when no choices are found
-            if choices.len() == 0 {
+            if choices.is_empty() {
                 if valid_positions.contains_key(&position) {
                     // try the valid position
                     choices.push(valid_positions[&position]);
@@ -462,14 +677,14 @@ impl Guesser {
             for (key, value) in output_frequency.iter() {
                 let correct = self.index.lookup[key].correct.len();
                 subtracted_freq.insert(
-                    key.clone(),
+                    *key,
                     value + self.difficulty - correct,
                 );
             }
 
             // sort choices
             self.choice_sort(
-                &mut choices,
+                choices,
                 position,
                 &subtracted_freq,
             );
@@ -481,22 +696,18 @@ impl Guesser {
             // we already appended it, so just move on
         }
 
-        panic!("failed to find a fail solution");
-
-        // backup
-        "0".repeat(self.difficulty)
     }
 
     // backtrack function
     // takes the current position, and the stack
     // returns whether we have ran out of places
-    fn revert(&self, position: usize, stack: &mut Vec<Vec<char>>) -> bool {
+    fn revert(&self, position: usize, stack: &mut [Vec<char>]) -> bool {
         let mut position = position as i32 - 1;
 
         while position >= 0 {
             stack[position as usize].pop();
 
-            if stack[position as usize].len() == 0 {
+            if stack[position as usize].is_empty() {
                 position -= 1;
                 continue;
             }
@@ -508,6 +719,9 @@ impl Guesser {
         position >= 0
     }
 
+    /// Returns the first guess passing the restrictions with the equality after
+    /// `expression_length` characters, the equation breaking the fewest
+    /// restrictions if the budget runs out, or None when there is none left
     fn backtrack(
         &self,
         valid: Vec<(char, &InfoLookup)>,
@@ -515,7 +729,8 @@ impl Guesser {
         valid_operators: usize,
         expression_length: usize,
         result_length: usize,
-    ) -> String {
+        budget: &mut Budget,
+    ) -> Option<String> {
 
         // create output array
         // this is to avoid recursion
@@ -527,20 +742,27 @@ impl Guesser {
 
         let mut output_frequency: HashMap<char, usize> = HashMap::new();
 
+        // the equation breaking the fewest restrictions, in case the budget runs out
+        let mut best: Option<(usize, String)> = None;
+
         'outer:
         loop {
+            if !budget.visit() {
+                return best.map(|(_, guess)| guess);
+            }
+
             // state variable: current guess ops left
             let mut ops_left: i32 = 2;
             // state variable: output characters freq
             for key in CHARS.iter() {
-                output_frequency.insert(key.clone(), 0);
+                output_frequency.insert(*key, 0);
             }
 
             // add top entries --- the current partial guess
             let mut output = vec![];
             for choices in stack.iter() {
-                if choices.len() > 0 {
-                    let item = choices.last().unwrap().clone();
+                if !choices.is_empty() {
+                    let item = *choices.last().unwrap();
 
                     if OPERATORS.contains(&item) {
                         ops_left -= 1;
@@ -586,6 +808,11 @@ impl Guesser {
                 );
 
                 if !passed {
+                    let violations = restriction_violations(&guess, &self.index);
+                    if best.as_ref().is_none_or(|(fewest, _)| violations < *fewest) {
+                        best = Some((violations, guess));
+                    }
+
                     // keep searching
                     if !self.revert(position, &mut stack) {
                         break 'outer;
@@ -593,7 +820,17 @@ impl Guesser {
                     continue 'outer;
                 }
 
-                return guess;
+                return Some(guess);
+            }
+
+            // prune prefixes whose result can no longer have the right length
+            if self.config.prune
+                && position > 0
+                && !result_may_fit(&output, expression_length, result_length) {
+                if !self.revert(position, &mut stack) {
+                    break 'outer;
+                }
+                continue 'outer;
             }
 
             // create filter
@@ -606,14 +843,14 @@ impl Guesser {
             );
 
 
-            let mut choices = &mut stack[position];
+            let choices = &mut stack[position];
 
             // use correct positions
             if valid_positions.contains_key(&position) {
                 let key = valid_positions[&position];
 
                 let lookup = &self.index.lookup[&key];
-                let char_freq = output_frequency.get(&key).unwrap().clone();
+                let char_freq = *output_frequency.get(&key).unwrap();
                 let too_much = (lookup.max == lookup.min) && (lookup.min <= char_freq);
                 if filter.contains(&key) || too_much {
                     // backtrack
@@ -636,24 +873,24 @@ impl Guesser {
                     continue;
                 }
 
-                // don't place incorrect values
-                if lookup.incorrect.contains(&position) {
+                // don't place characters the hints rule out here
+                if !self.index.allowed[position].contains(key) {
                     continue;
                 }
 
                 // don't add if we exceeded character maximum
-                let char_freq = output_frequency.get(&key).unwrap().clone();
+                let char_freq = *output_frequency.get(key).unwrap();
                 let too_much = (lookup.max == lookup.min) && (lookup.min <= char_freq);
                 if too_much {
                     continue;
                 }
 
-                choices.push(key.clone());
+                choices.push(*key);
             }
 
 
             // ignore when no choices are found
-            if choices.len() == 0 {
+            if choices.is_empty() {
                 if !self.revert(position, &mut stack) {
                     break 'outer;
                 }
@@ -662,7 +899,7 @@ impl Guesser {
 
             // sort choices
             self.choice_sort(
-                &mut choices,
+                choices,
                 position,
                 &HashMap::new(),
             );
@@ -674,9 +911,87 @@ impl Guesser {
             // we already appended it, so just move on
         }
 
-        panic!("failed to find a solution");
+        // the search ran out of options, so no equation with this length fits
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{all_secrets, consistent_secrets, set_colors};
 
-        // backup
-        "0".repeat(self.difficulty)
+    #[test]
+    fn candidate_guess_moves_past_ruled_out_positions() {
+        let info = vec![set_colors("2*6*9=108", "2*8%96=16")];
+        assert_eq!(consistent_secrets(9, &info), vec!["2*6*9=108"]);
+
+        for search in [SearchOrder::Backtrack, SearchOrder::Results] {
+            let config = GuesserConfig { search, ..GuesserConfig::default() };
+            let guesser = Guesser::new(InfoIndex::build(9, &info), 1, vec![], config);
+
+            // most secrets have the equality at 7, but none of those is consistent
+            let mut budget = Budget::new(None, None);
+            assert_eq!(guesser.candidate_guess(&[7, 5], &mut budget), "2*6*9=108");
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn weighted_equality_counts_the_consistent_secrets() {
+        let info = vec![set_colors("2*6*9=108", "2*8%96=16")];
+        let candidates = consistent_secrets(9, &info);
+
+        let config = GuesserConfig::default();
+        assert!(Guesser::needs_candidates(&config, &InfoIndex::build(9, &info)));
+
+        // the hints still allow the equality at 7, where most secrets have it
+        let mut guesser = Guesser::new(InfoIndex::build(9, &info), 1, candidates, config);
+        assert_eq!(guesser.deduce_equality(), vec![5]);
+
+        let solved = vec![set_colors("2*6*9=108", "2*6*9=108")];
+        assert!(!Guesser::needs_candidates(&GuesserConfig::default(), &InfoIndex::build(9, &solved)));
+    }
+
+    #[test]
+    fn equality_modes_explore_alike() {
+        // eight greens, and the rules explore on attempt 2 with at most two unknown
+        let mut pairs = vec![("12+34*5=182".to_string(), "12+34*51=83".to_string())];
+
+        let secrets = all_secrets(11);
+        for secret in secrets.iter().step_by(secrets.len() / 50) {
+            for guess in secrets.iter().step_by(secrets.len() / 20) {
+                pairs.push((secret.clone(), guess.clone()));
+            }
+        }
+
+        for (secret, guess) in pairs.iter() {
+            let info = vec![set_colors(secret, guess)];
+
+            for attempt in 2..=3 {
+                let assume = GuesserConfig { equality: EqualityMode::Assume, ..GuesserConfig::default() };
+                let mut assuming = Guesser::new(InfoIndex::build(11, &info), attempt, vec![], assume);
+                assuming.deduce_equality();
+
+                let mut weighting = Guesser::new(InfoIndex::build(11, &info), attempt, vec![], GuesserConfig::default());
+                weighting.deduce_equality();
+
+                assert_eq!(assuming.should_fail(), weighting.should_fail(), "{} against {}", guess, secret);
+            }
+        }
+    }
+
+    #[test]
+    fn hard_mode_guesses_a_candidate_when_the_budget_runs_out() {
+        let info = vec![set_colors("9*8-7=65", "13+45=58")];
+        let candidates = consistent_secrets(8, &info);
+        let hints = InfoIndex::build(8, &info);
+
+        let config = GuesserConfig { hard_mode: true, time_limit: None, node_limit: Some(1), ..GuesserConfig::default() };
+        let mut guesser = Guesser::new(hints.clone(), 1, candidates.clone(), config);
+        let guess = guesser.create_guess();
+
+        assert!(candidates.contains(&guess), "{}", guess);
+        assert!(passes_restrictions(&guess, &hints));
+        assert!(guesser.searches() < 10);
+    }
+}
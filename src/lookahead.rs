@@ -0,0 +1,204 @@
+//! The lookahead module looks two guesses ahead, scoring each guess by the
+//! feedback buckets it splits the candidates into and the best follow-up
+//! guess within each bucket.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use rand::seq::SliceRandom;
+use crate::budget::Budget;
+use crate::config::LookaheadConfig;
+use crate::matrix::{bucket_sizes, expected_bucket, FeedbackMatrix, pattern};
+
+/// Returns the lower bound on the number of guesses needed to solve a set of
+/// `size` candidates, one guess, plus another whenever that guess is wrong
+fn solve_bound(size: usize) -> f64 {
+    2.0 - 1.0 / size as f64
+}
+
+/// Returns the expected number of guesses to solve `bucket` starting with
+/// `guess`, bounding the guesses after it with `solve_bound`
+fn follow_up_score(guess: &str, bucket: &[&String], win: u32, budget: &mut Budget) -> Option<f64> {
+    let feedback: Option<Vec<u32>> = bucket.iter()
+        .map(|secret| budget.visit().then(|| pattern(secret.as_bytes(), guess.as_bytes())))
+        .collect();
+    let sizes = bucket_sizes(feedback?);
+
+    let total = bucket.len() as f64;
+    let rest: f64 = sizes.iter()
+        .filter(|(id, _)| **id != win)
+        .map(|(_, size)| *size as f64 / total * solve_bound(*size))
+        .sum();
+
+    Some(1.0 + rest)
+}
+
+/// Returns the expected number of guesses to solve `bucket` with the best
+/// follow-up guess among the bucket itself and the `pool`
+fn best_follow_up(
+    bucket: &[&String],
+    pool: &[String],
+    win: u32,
+    config: &LookaheadConfig,
+    budget: &mut Budget,
+) -> Option<f64> {
+    if bucket.len() == 1 {
+        return Some(1.0);
+    }
+
+    let mut rng = rand::thread_rng();
+    let members = bucket.choose_multiple(&mut rng, config.sample).map(|s| s.as_str());
+    let follow_ups = members.chain(pool.iter().map(|s| s.as_str()));
+
+    let mut best = f64::INFINITY;
+    for guess in follow_ups {
+        let score = follow_up_score(guess, bucket, win, budget)?;
+        if score < best {
+            best = score;
+        }
+    }
+
+    Some(best)
+}
+
+/// Returns the guess with the lowest expected total number of guesses out of
+/// the `seeds` and a sample of the `candidates`, looking two guesses ahead.
+/// When the budget runs out, the best guess scored so far is returned,
+/// falling back to the first seed. The shared matrix is cached in `cache` when given
+pub fn best_guess(
+    length: usize,
+    candidates: &[String],
+    seeds: &[String],
+    config: &LookaheadConfig,
+    cache: Option<&Path>,
+    budget: &mut Budget,
+) -> String {
+    // with two candidates left, guessing either one is already optimal
+    if candidates.len() <= 2 {
+        return candidates.first().unwrap_or(&seeds[0]).clone();
+    }
+
+    let mut rng = rand::thread_rng();
+    let mut pool: Vec<String> = seeds.to_vec();
+    pool.extend(candidates.choose_multiple(&mut rng, config.sample).cloned());
+
+    let mut seen = HashSet::new();
+    pool.retain(|guess| seen.insert(guess.clone()));
+
+    // the feedback of each guess of the pool against each candidate, read
+    // from the matrix of the turn. Each cell is charged to the budget, which
+    // may run out before the first ply is done
+    let matrix = FeedbackMatrix::for_turn(length, &pool, candidates, cache);
+    let columns: Vec<Option<usize>> = candidates.iter().map(|secret| matrix.column(secret)).collect();
+    let mut rows: Vec<Vec<u32>> = Vec::with_capacity(pool.len());
+    for guess in pool.iter() {
+        let mut row = Vec::with_capacity(candidates.len());
+        for (secret, column) in candidates.iter().zip(columns.iter()) {
+            if !budget.visit() {
+                return seeds[0].clone();
+            }
+
+            row.push(match (matrix.row(guess), column) {
+                (Some(row), Some(column)) => matrix.get(row, *column),
+                _ => pattern(secret.as_bytes(), guess.as_bytes()),
+            });
+        }
+
+        rows.push(row);
+    }
+
+    // rank the pool by a single ply, keeping the top k for the second ply
+    let mut ranked: Vec<(f64, usize)> = rows.iter()
+        .enumerate()
+        .map(|(guess, row)| (expected_bucket(row), guess))
+        .collect();
+    ranked.sort_by(|a, b| a.0.total_cmp(&b.0));
+    ranked.truncate(config.top_k);
+
+    let top: Vec<String> = ranked.iter()
+        .map(|(_, guess)| pool[*guess].clone())
+        .collect();
+
+    // every position of a candidate against itself is green
+    let win = pattern(candidates[0].as_bytes(), candidates[0].as_bytes());
+    let total = candidates.len() as f64;
+    let mut best = (f64::INFINITY, seeds[0].clone());
+
+    'guesses:
+    for (_, guess) in ranked.iter() {
+        // split the candidates into feedback buckets
+        let mut buckets: HashMap<u32, Vec<&String>> = HashMap::new();
+        for (id, candidate) in rows[*guess].iter().zip(candidates.iter()) {
+            buckets.entry(*id).or_default().push(candidate);
+        }
+
+        let mut score = 1.0;
+        for (id, bucket) in buckets.iter() {
+            if *id == win {
+                continue;
+            }
+
+            match best_follow_up(bucket, &top, win, config, budget) {
+                Some(follow_up) => score += bucket.len() as f64 / total * follow_up,
+                None => break 'guesses,
+            }
+        }
+
+        if score < best.0 {
+            best = (score, pool[*guess].clone());
+        }
+    }
+
+    best.1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::all_secrets;
+
+    fn config() -> LookaheadConfig {
+        LookaheadConfig { top_k: 4, sample: 20, time_limit: None, node_limit: None }
+    }
+
+    #[test]
+    fn returns_the_first_seed_without_budget() {
+        let candidates: Vec<String> = all_secrets(7).into_iter().step_by(50).collect();
+        let seeds = vec!["1+2*3=7".to_string(), "9-8+1=2".to_string()];
+
+        let mut budget = Budget::new(None, Some(0));
+        assert_eq!(best_guess(7, &candidates, &seeds, &config(), None, &mut budget), seeds[0]);
+    }
+
+    #[test]
+    fn returns_the_last_candidate() {
+        let candidates = vec!["12+3=15".to_string()];
+        let seeds = vec!["1+2*3=7".to_string()];
+
+        let mut budget = Budget::new(None, None);
+        assert_eq!(best_guess(7, &candidates, &seeds, &config(), None, &mut budget), candidates[0]);
+    }
+
+    #[test]
+    fn picks_from_the_seeds_and_candidates() {
+        let candidates: Vec<String> = all_secrets(7).into_iter().step_by(100).collect();
+        let seeds = vec!["1+2*3=7".to_string()];
+
+        let mut budget = Budget::new(None, None);
+        let guess = best_guess(7, &candidates, &seeds, &config(), None, &mut budget);
+        assert!(guess == seeds[0] || candidates.contains(&guess), "{}", guess);
+    }
+
+    #[test]
+    fn follow_ups_count_the_guesses_left() {
+        let (a, b) = ("12+3=15".to_string(), "13+2=15".to_string());
+        let bucket = vec![&a, &b];
+        let win = pattern(a.as_bytes(), a.as_bytes());
+        let mut budget = Budget::new(None, None);
+
+        // guessing one of the two wins half the time, and else takes one more guess
+        assert_eq!(follow_up_score(&a, &bucket, win, &mut budget), Some(1.5));
+
+        let mut budget = Budget::new(None, Some(1));
+        assert_eq!(follow_up_score(&a, &bucket, win, &mut budget), None);
+    }
+}
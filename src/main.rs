@@ -1,35 +1,554 @@
 use std::env;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::process;
 use std::time::Instant;
-use crate::game::{AllInfo};
+use crate::config::GuesserConfig;
+use crate::game::{AllInfo, Color, Rules};
+use crate::referee::Referee;
+use crate::session::Session;
+use crate::share::{Palette, share_text};
+use crate::tolerant::TolerantSolver;
+use crate::unknown::UnknownLengthSolver;
 
+mod budget;
+mod config;
+mod daily;
+mod equations;
 mod guesser;
 mod game;
+mod lookahead;
+mod mastermind;
+mod matrix;
+mod multi;
+mod play;
+mod referee;
+mod reverse;
+mod session;
+mod share;
+mod summary;
 mod test;
+mod tolerant;
+mod unknown;
 
-fn create_better_guess(info: &AllInfo, difficulty: usize) -> String {
-    let mut guesser = guesser::Guesser::new(difficulty, info);
-    guesser.create_guess()
+fn create_better_guess(session: &mut Session, info: &AllInfo, difficulty: usize) -> String {
+    session.sync(difficulty, info);
+    session.create_guess()
 }
 
-fn benchmark() {
+/// Same as `create_better_guess`, but reports the search effort on stderr
+fn create_reported_guess(session: &mut Session, info: &AllInfo, difficulty: usize) -> String {
+    let start = Instant::now();
+    let guess = create_better_guess(session, info, difficulty);
+    eprintln!("({} nodes in {:?})", session.searches(), start.elapsed());
+    guess
+}
+
+fn benchmark(config: &GuesserConfig, rules: &Rules, num_secrets: usize, repeats: usize) {
+    let normal = (GuesserConfig { hard_mode: false, ..config.clone() }, Rules { hard_mode: false, ..rules.clone() });
+    let hard = (GuesserConfig { hard_mode: true, ..config.clone() }, Rules { hard_mode: true, ..rules.clone() });
+
+    // hard mode has no effect when the feedback lies
+    let modes = if rules.lies > 0 { vec![("", &normal)] } else { vec![("", &normal), (" (hard mode)", &hard)] };
+
     for difficulty in 7..16 {
-        println!("Difficulty {}", difficulty);
-        let start = Instant::now();
+        for (mode, (config, rules)) in modes.iter() {
+            println!("Difficulty {}{}", difficulty, mode);
+            let start = Instant::now();
 
-        test::test(difficulty, create_better_guess);
+            let mut session = Session::new(difficulty, config.clone());
+            let mut tolerant = TolerantSolver::new(difficulty, rules.lies, config.clone());
+            test::test(
+                difficulty,
+                num_secrets,
+                repeats,
+                rules,
+                &mut |info, difficulty| if rules.lies > 0 {
+                    tolerant.create_guess(info)
+                } else {
+                    create_better_guess(&mut session, info, difficulty)
+                },
+            );
 
-        println!("took {:?} (for 1000 secrets, 100x each)\n", start.elapsed());
+            println!("took {:?} (for {} secrets, {}x each)\n", start.elapsed(), num_secrets, repeats);
+        }
     }
 }
 
+/// Builds the full secret against secret matrix of a length, the one strategies
+/// read their feedback from, caching it in the configured directory if any
+fn precompute_matrix(difficulty: usize, config: &GuesserConfig) {
+    let start = Instant::now();
+    let Some(matrix) = matrix::FeedbackMatrix::shared(difficulty, config.cache_directory.as_deref()) else {
+        println!("length {} has too many secrets for a full matrix", difficulty);
+        return;
+    };
+
+    println!(
+        "matrix {}x{} of length {} ready, took {:?}",
+        matrix.guesses().len(),
+        matrix.secrets().len(),
+        matrix.length(),
+        start.elapsed(),
+    );
+
+    // report the guess with the smallest worst case bucket
+    let everything: Vec<usize> = (0..matrix.secrets().len()).collect();
+    let best = (0..matrix.guesses().len())
+        .map(|guess| (matrix.bucket_sizes(guess, &everything).into_values().max().unwrap_or(0), guess))
+        .min();
+
+    if let Some((worst, guess)) = best {
+        println!("best minimax guess {} leaves at most {} secrets", matrix.guesses()[guess], worst);
+    }
+}
+
+/// Returns why `secret` is not a true equation, if it is not
+fn check_secret(secret: &str) -> Result<(), String> {
+    game::check_secret(secret)
+        .map_err(|rejection| format!("invalid secret '{}': {}", secret, rejection))
+}
+
+/// Returns a new game of a secret, exiting when it is not a true equation
+fn new_game(secret: &str, rules: Rules) -> game::Game {
+    game::Game::new(secret, rules).unwrap_or_else(|rejection| {
+        eprintln!("invalid secret '{}': {}", secret, rejection);
+        process::exit(1);
+    })
+}
+
+/// Prints what the hints of some guesses on a secret reveal, or returns why
+/// the secret is not a true equation or a guess cannot be played against it
+fn summarise(secret: &str, guesses: &[String], as_json: bool) -> Result<(), String> {
+    check_secret(secret)?;
+
+    let referee = Referee::new(secret.len(), &Rules::default());
+    for guess in guesses.iter() {
+        referee.check(guess, None)
+            .map_err(|rejection| format!("invalid guess '{}': {}", guess, rejection))?;
+    }
+
+    let info: AllInfo = guesses.iter()
+        .map(|guess| game::set_colors(secret, guess))
+        .collect();
+
+    let index = game::InfoIndex::build(secret.len(), &info);
+    let summary = summary::Summary::new(&index);
+    if as_json {
+        println!("{}", summary.to_json());
+    } else {
+        println!("{}", summary);
+    }
+
+    Ok(())
+}
 
+/// Prints the guesses that could have given each row of colors on a secret,
+/// up to `limit` per row, or returns why the secret is not a true equation
+fn reverse(secret: &str, rows: &[Vec<Color>], equations_only: bool, limit: usize) -> Result<(), String> {
+    check_secret(secret)?;
+
+    let equations = if equations_only { game::all_secrets(secret.len()) } else { vec![] };
+
+    for (i, colors) in rows.iter().enumerate() {
+        if colors.len() != secret.len() {
+            println!("row {}: has {} colors, expected {}", i + 1, colors.len(), secret.len());
+            continue;
+        }
+
+        let (guesses, count) = if equations_only {
+            let guesses = reverse::equations_for(secret, colors, &equations);
+            let count = format!("{} equation{}", guesses.len(), if guesses.len() == 1 { "" } else { "s" });
+            (guesses.into_iter().take(limit).collect(), count)
+        } else {
+            match reverse::guesses_for(secret, colors, limit) {
+                (guesses, true) => (guesses, format!("more than {} guesses", limit)),
+                (guesses, false) => {
+                    let count = format!("{} guess{}", guesses.len(), if guesses.len() == 1 { "" } else { "es" });
+                    (guesses, count)
+                }
+            }
+        };
+
+        println!("row {}: {}", i + 1, count);
+        for guess in guesses.iter() {
+            println!("  {}", guess);
+        }
+    }
+
+    Ok(())
+}
+
+/// Solves a number of random secrets at once, printing each guess with the
+/// feedback of every board
+fn solve_boards(boards: usize, difficulty: usize, config: &GuesserConfig, rules: Rules) {
+    let secrets: Vec<String> = (0..boards).map(|_| game::create_secret(difficulty)).collect();
+    let mut game = multi::MultiGame::new(&secrets, rules).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(1);
+    });
+    let mut strategy = multi::MultiStrategy::new(boards, difficulty, config);
+
+    while !game.is_won() && !game.is_lost() {
+        let guess = strategy.create_guess(&game);
+        let feedback = game.guess(&guess).unwrap_or_else(|error| {
+            eprintln!("invalid guess '{}': {}", guess, error);
+            process::exit(1);
+        });
+
+        let rows: Vec<String> = feedback.iter()
+            .map(|row| match row {
+                Some(row) => share::share_row(row, Palette::Ascii),
+                None => " ".repeat(difficulty),
+            })
+            .collect();
+        println!("{}  {}", guess, rows.join("  "));
+    }
+
+    println!();
+    for (secret, board) in secrets.iter().zip(game.boards()) {
+        if board.is_won() {
+            println!("{} solved in {}", secret, board.history().len());
+        } else {
+            println!("{} not solved", secret);
+        }
+    }
+}
+
+/// Solves a secret in the variant only reporting the counts of greens and yellows
+fn solve_counts(secret: &str, rules: &Rules) {
+    let mut game = mastermind::CountGame::new(secret, rules.clone()).unwrap_or_else(|rejection| {
+        eprintln!("invalid secret '{}': {}", secret, rejection);
+        process::exit(1);
+    });
+    let mut solver = mastermind::CountSolver::new(secret.len());
+
+    while !game.is_won() && !game.is_lost() {
+        let guess = match solver.create_guess(game.history()) {
+            Ok(guess) => guess,
+            Err(error) => {
+                println!("{}", error);
+                return;
+            }
+        };
+
+        match game.guess(&guess) {
+            Ok(counts) => println!("{}  {}", guess, counts),
+            Err(error) => {
+                println!("invalid guess '{}': {}", guess, error);
+                return;
+            }
+        }
+    }
+
+    if game.is_lost() {
+        println!("lost after {} guesses", game.history().len());
+    }
+}
+
+/// Solves a secret without telling the solver its length, in a game taking
+/// guesses of any length, printing with each guess the lengths it was picked
+/// from. Returns the game once it is over, None if it was given up
+fn solve_unknown_length(secret: &str, rules: &Rules, config: &GuesserConfig) -> Option<game::Game> {
+    let mut game = new_game(secret, Rules { any_length: true, ..rules.clone() });
+    let mut solver = UnknownLengthSolver::new(config.clone());
+
+    while !game.is_won() && !game.is_lost() {
+        let guess = match solver.create_guess(game.history()) {
+            Ok(guess) => guess,
+            Err(error) => {
+                println!("{}", error);
+                return None;
+            }
+        };
+
+        let lengths: Vec<String> = solver.lengths().iter().map(|length| length.to_string()).collect();
+        match game.guess(&guess) {
+            Ok(feedback) => println!("{:15}  {:15}  lengths {}", guess, share::share_row(&feedback, Palette::Ascii), lengths.join(" ")),
+            Err(error) => {
+                println!("invalid guess '{}': {}", guess, error);
+                return None;
+            }
+        }
+    }
+
+    if game.is_lost() {
+        println!("lost after {} guesses", game.history().len());
+    }
+
+    Some(game)
+}
+
+/// Removes a `--flag` from the arguments, returning if it was present
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    match args.iter().position(|arg| arg == flag) {
+        Some(position) => {
+            args.remove(position);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Removes an `--option value` pair from the arguments, returning the value
+fn take_option(args: &mut Vec<String>, option: &str) -> Option<String> {
+    let position = args.iter().position(|arg| arg == option)?;
+    if position + 1 >= args.len() {
+        eprintln!("missing value for {}", option);
+        process::exit(1);
+    }
+
+    let value = args.remove(position + 1);
+    args.remove(position);
+    Some(value)
+}
 
 fn main() {
-    // benchmark();
-    let args: Vec<String> = env::args().collect();
-    for arg in args[1..].iter() {
-        println!("secret {}:", arg);
-        test::test_once(arg, create_better_guess);
-        println!();
+    let mut args: Vec<String> = env::args().skip(1).collect();
+
+    let mut config = match take_option(&mut args, "--config") {
+        Some(path) => GuesserConfig::load(Path::new(&path)).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            process::exit(1);
+        }),
+        None => GuesserConfig::default(),
+    };
+
+    let report = take_flag(&mut args, "--nodes");
+    let as_json = take_flag(&mut args, "--json");
+    let free = take_flag(&mut args, "--free");
+    let reveal = take_flag(&mut args, "--reveal");
+    let share = take_flag(&mut args, "--share");
+    let equations = take_flag(&mut args, "--equations");
+    let adversarial = take_flag(&mut args, "--adversarial");
+    let counts = take_flag(&mut args, "--counts");
+    let unknown_length = take_flag(&mut args, "--unknown-length");
+    let palette = if take_flag(&mut args, "--ascii") {
+        Palette::Ascii
+    } else if take_flag(&mut args, "--contrast") {
+        Palette::HighContrast
+    } else {
+        Palette::Emoji
+    };
+
+    // hard mode binds both the guesser and the games it plays
+    config.hard_mode |= take_flag(&mut args, "--hard");
+    let max_guesses: Option<usize> = take_option(&mut args, "--max-guesses").map(|max_guesses| {
+        max_guesses.parse().unwrap_or_else(|_| {
+            eprintln!("--max-guesses must be a number");
+            process::exit(1);
+        })
+    });
+    let lies: usize = take_option(&mut args, "--lies").map_or(0, |lies| {
+        lies.parse().unwrap_or_else(|_| {
+            eprintln!("--lies must be a number");
+            process::exit(1);
+        })
+    });
+
+    // as in Fibble, where a lie per row gets 3 more guesses
+    let mut rules = Rules {
+        hard_mode: config.hard_mode,
+        max_guesses: max_guesses.unwrap_or(game::MAX_GUESSES + 3 * lies),
+        lies,
+        ..Rules::default()
+    };
+
+    match args.first().map(|arg| arg.as_str()) {
+        Some("benchmark") => {
+            let num_secrets = args.get(1).and_then(|arg| arg.parse().ok()).unwrap_or(1000);
+            let repeats = args.get(2).and_then(|arg| arg.parse().ok()).unwrap_or(100);
+            benchmark(&config, &rules, num_secrets, repeats);
+        }
+        Some("summary") => {
+            if args.len() < 2 {
+                eprintln!("usage: focdle summary [secret] [guess]* [--json]");
+                process::exit(1);
+            }
+
+            if let Err(error) = summarise(&args[1], &args[2..], as_json) {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+        }
+        Some("play") => {
+            let difficulty = args.get(1).map_or(Some(8), |arg| arg.parse().ok())
+                .filter(|difficulty| (7..=15).contains(difficulty))
+                .unwrap_or_else(|| {
+                    eprintln!("usage: focdle play [length=8] [--free] [--adversarial], with a length from 7 to 15");
+                    process::exit(1);
+                });
+
+            rules.equations_only = !free;
+            let (game, title) = play::new_game(difficulty, adversarial, rules).unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                process::exit(1);
+            });
+            play::play(game, &title, palette);
+        }
+        Some("adversary") => {
+            let difficulty = args.get(1).map_or(Some(8), |arg| arg.parse().ok())
+                .filter(|difficulty| (7..=15).contains(difficulty))
+                .unwrap_or_else(|| {
+                    eprintln!("usage: focdle adversary [length=8], with a length from 7 to 15");
+                    process::exit(1);
+                });
+
+            // only the default solver tolerates lies
+            if rules.lies > 0 {
+                eprintln!("--lies does not apply to adversary, as its solver trusts every hint");
+                process::exit(1);
+            }
+
+            let mut session = Session::new(difficulty, config.clone());
+            let (outcome, guessed) = test::play_out(
+                game::Game::adversarial(difficulty, rules),
+                &mut |info, difficulty| create_better_guess(&mut session, info, difficulty),
+            );
+
+            match outcome {
+                test::Outcome::Won => println!("won in {} guesses", guessed.len()),
+                test::Outcome::Lost => println!("lost after {} guesses", guessed.len()),
+            }
+        }
+        Some("multi") => {
+            let boards = args.get(1).map_or(Some(4), |arg| arg.parse().ok()).filter(|boards| *boards > 0);
+            let difficulty = args.get(2).map_or(Some(8), |arg| arg.parse().ok())
+                .filter(|difficulty| (7..=15).contains(difficulty));
+            let (Some(boards), Some(difficulty)) = (boards, difficulty) else {
+                eprintln!("usage: focdle multi [boards=4] [length=8], with a length from 7 to 15");
+                process::exit(1);
+            };
+
+            if rules.lies > 0 {
+                eprintln!("--lies does not apply to multi, as its solver trusts every hint");
+                process::exit(1);
+            }
+
+            // as in Quordle, where 4 boards get 9 guesses
+            rules.max_guesses = max_guesses.unwrap_or(boards + 5);
+            solve_boards(boards, difficulty, &config, rules);
+        }
+        Some("daily") => {
+            let day = take_option(&mut args, "--date")
+                .map_or(Ok(daily::today()), |date| daily::parse_date(&date));
+            let length = take_option(&mut args, "--length")
+                .map_or(Some(8), |length| length.parse().ok());
+            let window = take_option(&mut args, "--window")
+                .map_or(Some(daily::WINDOW), |window| window.parse().ok());
+
+            let puzzle = match (day, length, window) {
+                (Ok(day), Some(length), Some(window)) => daily::puzzle(day, length, window),
+                (Err(error), _, _) => Err(error),
+                _ => Err("usage: focdle daily [--date YYYY-MM-DD] [--length N] [--window days] [--reveal]".to_string()),
+            };
+
+            let puzzle = puzzle.unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                process::exit(1);
+            });
+
+            if reveal {
+                println!("{}", puzzle.secret);
+            } else {
+                let title = format!("focdle #{} ({})", puzzle.number, puzzle.date);
+                println!("{}", title);
+                rules.equations_only = !free;
+                play::play(new_game(&puzzle.secret, rules), &title, palette);
+            }
+        }
+        Some("reverse") => {
+            let limit = take_option(&mut args, "--limit")
+                .map_or(Some(20), |limit| limit.parse().ok());
+            let (Some(secret), Some(limit)) = (args.get(1), limit) else {
+                eprintln!("usage: focdle reverse [secret] [row]* [--equations] [--limit N]");
+                process::exit(1);
+            };
+
+            // without rows on the command line, read a pasted share text
+            let rows: Vec<Vec<Color>> = if args.len() > 2 {
+                args[2..].iter().filter_map(|row| share::parse_row(row)).collect()
+            } else {
+                io::stdin().lock().lines()
+                    .map_while(Result::ok)
+                    .filter_map(|line| share::parse_row(&line))
+                    .collect()
+            };
+
+            if let Err(error) = reverse(secret, &rows, equations, limit) {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+        }
+        Some("matrix") => {
+            let difficulty = args.get(1).and_then(|arg| arg.parse().ok())
+                .filter(|difficulty| (7..=15).contains(difficulty))
+                .unwrap_or_else(|| {
+                    eprintln!("usage: focdle matrix [length], with a length from 7 to 15");
+                    process::exit(1);
+                });
+            precompute_matrix(difficulty, &config);
+        }
+        _ => {
+            // counts have no colors to share
+            if share && counts {
+                eprintln!("--share does not apply to --counts, as its feedback has no colors");
+                process::exit(1);
+            }
+
+            // only the default solver tolerates lies
+            if rules.lies > 0 && (counts || unknown_length) {
+                eprintln!("--lies does not apply to --counts or --unknown-length, as their solvers trust every hint");
+                process::exit(1);
+            }
+
+            for arg in args.iter() {
+                if let Err(error) = check_secret(arg) {
+                    eprintln!("{}", error);
+                    process::exit(1);
+                }
+
+                println!("secret {}:", arg);
+                if counts {
+                    solve_counts(arg, &rules);
+                    println!();
+                    continue;
+                }
+
+                if unknown_length {
+                    let game = solve_unknown_length(arg, &rules, &config);
+                    if let Some(game) = game.filter(|_| share) {
+                        println!();
+                        println!("{}", share_text("focdle (unknown length)", game.history(), game.is_won(), game.max_guesses(), palette));
+                    }
+                    println!();
+                    continue;
+                }
+
+                let mut session = Session::new(arg.len(), config.clone());
+                let (outcome, guessed) = if rules.lies > 0 {
+                    let mut tolerant = TolerantSolver::new(arg.len(), rules.lies, config.clone());
+                    test::test_once(arg, &rules, &mut |info, _| tolerant.create_guess(info))
+                } else if report {
+                    test::test_once(arg, &rules, &mut |info, difficulty| create_reported_guess(&mut session, info, difficulty))
+                } else {
+                    test::test_once(arg, &rules, &mut |info, difficulty| create_better_guess(&mut session, info, difficulty))
+                };
+
+                if outcome == test::Outcome::Lost {
+                    println!("lost after {} guesses", guessed.len());
+                }
+
+                if share {
+                    let history: AllInfo = guessed.iter()
+                        .map(|guess| game::set_colors(arg, guess))
+                        .collect();
+                    let title = format!("focdle {}", arg.len());
+                    println!();
+                    println!("{}", share_text(&title, &history, outcome == test::Outcome::Won, rules.max_guesses, palette));
+                }
+                println!();
+            }
+        }
     }
 }
@@ -0,0 +1,215 @@
+//! The mastermind module plays a variant where the host only reports how
+//! many tiles of a guess are green and yellow, not which ones. Without
+//! positions there is no `InfoIndex`, so the solver filters the secrets.
+
+use std::fmt;
+use crate::game::{all_secrets, Color, ColorInfo, Game, GuessError, Rules, set_colors};
+use crate::matrix::{expected_bucket, pattern, POOL_SAMPLE, SCORE_SAMPLE, spread};
+use crate::referee::Rejection;
+
+/// The feedback of a guess in the count-only variant
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Counts {
+    pub greens: usize,
+    pub yellows: usize,
+}
+
+impl Counts {
+    /// Returns the counts of `guess` against `secret`
+    pub fn of(secret: &str, guess: &str) -> Counts {
+        Counts::of_row(&set_colors(secret, guess))
+    }
+
+    /// Returns the counts of the colors of a feedback row
+    pub fn of_row(row: &[ColorInfo]) -> Counts {
+        Counts {
+            greens: row.iter().filter(|info| info.color == Color::Green).count(),
+            yellows: row.iter().filter(|info| info.color == Color::Yellow).count(),
+        }
+    }
+
+    /// Returns the counts of a pattern id of a length, equivalent to `Counts::of`
+    /// for the secret and guess of the pattern, without any allocation
+    fn from_pattern(id: u32, length: usize) -> Counts {
+        let mut counts = Counts { greens: 0, yellows: 0 };
+        let mut id = id;
+        for _ in 0..length {
+            match id % 3 {
+                2 => counts.greens += 1,
+                1 => counts.yellows += 1,
+                _ => {}
+            }
+            id /= 3;
+        }
+
+        counts
+    }
+}
+
+impl fmt::Display for Counts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} green, {} yellow", self.greens, self.yellows)
+    }
+}
+
+/// The `CountGame` struct is a game against a secret that only reports
+/// counts, playing a `Game` and counting the colors of its feedback
+pub struct CountGame {
+    game: Game,
+    /// whether every guess must be a possible secret given the earlier counts,
+    /// as the positional hints the game would hold guesses to are not revealed
+    hard_mode: bool,
+    history: Vec<(String, Counts)>,
+}
+
+impl CountGame {
+    /// Returns a new count-only game with the given secret and rules, or why
+    /// the secret is not a true equation. In hard mode, every guess must be a
+    /// possible secret given the earlier counts
+    pub fn new(secret: &str, rules: Rules) -> Result<CountGame, Rejection> {
+        Ok(CountGame {
+            hard_mode: rules.hard_mode,
+            game: Game::new(secret, Rules { hard_mode: false, ..rules })?,
+            history: vec![],
+        })
+    }
+
+    /// Returns every guess made so far with its counts
+    pub fn history(&self) -> &[(String, Counts)] {
+        &self.history
+    }
+
+    /// Checks and makes a guess, returning its counts
+    pub fn guess(&mut self, guess: &str) -> Result<Counts, GuessError> {
+        self.game.check(guess)?;
+
+        if self.hard_mode && !self.history.iter().all(|(earlier, counts)| Counts::of(guess, earlier) == *counts) {
+            return Err(GuessError::Rejected(Rejection::IgnoresHints));
+        }
+
+        let counts = Counts::of_row(&self.game.guess(guess)?);
+        self.history.push((guess.to_string(), counts));
+
+        Ok(counts)
+    }
+
+    /// Returns whether the last guess was the secret
+    pub fn is_won(&self) -> bool {
+        self.game.is_won()
+    }
+
+    /// Returns whether every allowed guess was made without finding the secret
+    pub fn is_lost(&self) -> bool {
+        self.game.is_lost()
+    }
+}
+
+/// The `CountSolver` struct guesses for a `CountGame` by keeping every secret
+/// consistent with the counts so far
+pub struct CountSolver {
+    difficulty: usize,
+    /// the secrets consistent with the rows seen, gathered on the first guess
+    candidates: Option<Vec<String>>,
+    /// the number of rows of the history already filtered by
+    seen: usize,
+}
+
+impl CountSolver {
+    pub fn new(difficulty: usize) -> CountSolver {
+        CountSolver {
+            difficulty,
+            candidates: None,
+            seen: 0,
+        }
+    }
+
+    /// Returns the candidate whose counts split a sample of the candidates
+    /// into the smallest expected group, or an error when no secret of the
+    /// difficulty gives every count so far
+    pub fn create_guess(&mut self, history: &[(String, Counts)]) -> Result<String, String> {
+        let (difficulty, seen) = (self.difficulty, self.seen);
+        let candidates = self.candidates.get_or_insert_with(|| all_secrets(difficulty));
+
+        for (guess, counts) in history[seen..].iter() {
+            candidates.retain(|secret| {
+                Counts::from_pattern(pattern(secret.as_bytes(), guess.as_bytes()), difficulty) == *counts
+            });
+        }
+        self.seen = history.len();
+
+        let pool = spread(candidates, POOL_SAMPLE);
+        let sample = spread(candidates, SCORE_SAMPLE);
+        pool.into_iter()
+            .map(|guess| (expected_remaining(guess, &sample, difficulty), guess))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, guess)| guess.clone())
+            .ok_or_else(|| format!("no secret of length {} gives every count so far", difficulty))
+    }
+}
+
+/// Returns the expected number of sampled secrets sharing the counts of a guess
+fn expected_remaining(guess: &str, sample: &[&String], difficulty: usize) -> f64 {
+    expected_bucket(sample.iter().map(|secret| {
+        Counts::from_pattern(pattern(secret.as_bytes(), guess.as_bytes()), difficulty)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_of_a_pattern_agree_with_the_colors() {
+        let secrets = all_secrets(8);
+        for secret in secrets.iter().step_by(211) {
+            for guess in secrets.iter().step_by(307) {
+                let id = pattern(secret.as_bytes(), guess.as_bytes());
+                assert_eq!(Counts::from_pattern(id, 8), Counts::of(secret, guess), "{} {}", secret, guess);
+            }
+        }
+    }
+
+    #[test]
+    fn solver_refuses_counts_no_secret_gives() {
+        let mut solver = CountSolver::new(7);
+        let history = vec![("1+1+1=3".to_string(), Counts { greens: 0, yellows: 0 })];
+
+        assert!(solver.create_guess(&history).is_err());
+    }
+
+    #[test]
+    fn solver_finds_the_secret() {
+        let mut game = CountGame::new("12+3*4=24", Rules { max_guesses: 20, ..Rules::default() }).unwrap();
+        let mut solver = CountSolver::new(9);
+
+        while !game.is_won() && !game.is_lost() {
+            let guess = solver.create_guess(game.history()).unwrap();
+            game.guess(&guess).unwrap();
+        }
+
+        assert!(game.is_won());
+    }
+
+    #[test]
+    fn games_count_the_colors_of_the_wrapped_game() {
+        let mut game = CountGame::new("9*8-7=65", Rules { max_guesses: 2, ..Rules::default() }).unwrap();
+        assert_eq!(game.guess("13+45=58"), Ok(Counts::of("9*8-7=65", "13+45=58")));
+        assert_eq!(game.guess("9*8-7=6"), Err(GuessError::Rejected(Rejection::WrongLength { expected: 8, found: 7 })));
+        assert_eq!(game.guess("9*8-7=65"), Ok(Counts { greens: 8, yellows: 0 }));
+
+        assert!(game.is_won());
+        assert_eq!(game.guess("9*8-7=65"), Err(GuessError::GameOver));
+        assert!(CountGame::new("12+34=46", Rules::default()).is_err());
+    }
+
+    #[test]
+    fn hard_mode_holds_guesses_to_the_counts() {
+        let mut game = CountGame::new("9*8-7=65", Rules { hard_mode: true, ..Rules::default() }).unwrap();
+        let counts = game.guess("13+45=58").unwrap();
+
+        // a guess giving other counts against the first cannot be the secret
+        let ignoring = "2*3+4=10";
+        assert_ne!(Counts::of(ignoring, "13+45=58"), counts);
+        assert_eq!(game.guess(ignoring), Err(GuessError::Rejected(Rejection::IgnoresHints)));
+    }
+}
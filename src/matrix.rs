@@ -0,0 +1,534 @@
+//! The matrix module precomputes the feedback pattern of every
+//! (guess, secret) pair for a length, so that strategies and analysis
+//! tools never have to call `set_colors` in their inner loops. A full
+//! matrix of every secret against every secret only fits for length 7,
+//! every other length builds a matrix of the guesses a turn scores against
+//! the candidates left.
+
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hash;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use lazy_static::lazy_static;
+use crate::game::{all_secrets, Color, count_secrets};
+
+lazy_static! {
+    // the full matrices loaded so far by length, None when a length has too many secrets
+    static ref SHARED: Mutex<HashMap<usize, Option<Arc<FeedbackMatrix>>>> = Mutex::new(HashMap::new());
+}
+
+/// the magic bytes at the start of a cached matrix file
+const MAGIC: &[u8; 4] = b"FOCM";
+
+/// the cache file format version, bump when the layout changes
+const VERSION: u8 = 1;
+
+/// the largest length whose pattern ids fit in a u16 (3^10 < 2^16)
+const NARROW_LENGTH: usize = 10;
+
+/// the largest full matrix of every secret against every secret, in cells
+const FULL_LIMIT: usize = 1 << 28;
+
+/// the number of candidates the sampling strategies pick their guess from
+pub const POOL_SAMPLE: usize = 100;
+
+/// the number of candidates the sampling strategies score their guesses against
+pub const SCORE_SAMPLE: usize = 500;
+
+/// Returns the pattern id of a row of colors, a base 3 number where the
+/// digit at position `i` is 0 for grey, 1 for yellow and 2 for green
+pub fn pattern_id(colors: impl IntoIterator<Item = Color>) -> u32 {
+    let mut id = 0;
+    let mut weight = 1;
+    for color in colors {
+        id += weight * match color {
+            Color::Grey => 0,
+            Color::Yellow => 1,
+            Color::Green => 2,
+        };
+
+        weight *= 3;
+    }
+
+    id
+}
+
+/// Returns the pattern id of `guess` against `secret`, equivalent to
+/// `pattern_id` of the colors of `set_colors(secret, guess)` but without any allocation
+pub fn pattern(secret: &[u8], guess: &[u8]) -> u32 {
+    // ascii character frequencies of the secret, minus the greens
+    let mut secret_freq = [0u8; 128];
+    for (i, s) in secret.iter().enumerate() {
+        if guess.get(i) != Some(s) {
+            secret_freq[*s as usize] += 1;
+        }
+    }
+
+    let mut id = 0;
+    let mut weight = 1;
+    for (i, g) in guess.iter().enumerate() {
+        if secret.get(i) == Some(g) {
+            id += 2 * weight;
+        } else if secret_freq[*g as usize] > 0 {
+            secret_freq[*g as usize] -= 1;
+            id += weight;
+        }
+
+        weight *= 3;
+    }
+
+    id
+}
+
+/// Returns up to `count` evenly spaced items of a list
+pub fn spread<T>(list: &[T], count: usize) -> Vec<&T> {
+    let step = list.len().div_ceil(count).max(1);
+    list.iter().step_by(step).collect()
+}
+
+/// Returns the number of secrets in each feedback bucket, given the feedback
+/// of a guess against each secret
+pub fn bucket_sizes<K: Eq + Hash>(feedback: impl IntoIterator<Item = K>) -> HashMap<K, usize> {
+    let mut sizes: HashMap<K, usize> = HashMap::new();
+    for key in feedback {
+        *sizes.entry(key).or_insert(0) += 1;
+    }
+
+    sizes
+}
+
+/// Returns the expected size of the bucket of a random secret, given the
+/// feedback of a guess against each secret
+pub fn expected_bucket<K: Eq + Hash>(feedback: impl IntoIterator<Item = K>) -> f64 {
+    let sizes = bucket_sizes(feedback);
+    let total: usize = sizes.values().sum();
+    let squares: usize = sizes.values().map(|size| size * size).sum();
+    squares as f64 / total as f64
+}
+
+/// Returns the expected number of `candidates` left after a guess, estimated
+/// on a sample of them. A guess that is itself a candidate is the secret with
+/// a chance of one in all of them, which leaves none
+pub fn sampled_remaining(guess: &str, sample: &[&String], candidates: usize, is_candidate: bool) -> f64 {
+    let bucket = expected_bucket(sample.iter().map(|secret| pattern(secret.as_bytes(), guess.as_bytes())));
+    let expected = bucket * candidates as f64 / sample.len() as f64;
+
+    if is_candidate {
+        expected - 1.0 / candidates as f64
+    } else {
+        expected
+    }
+}
+
+/// The pattern ids, stored in the narrowest integer that fits the length
+enum Cells {
+    Narrow(Vec<u16>),
+    Wide(Vec<u32>),
+}
+
+/// The `FeedbackMatrix` struct holds the pattern id of every guess against
+/// every secret, row-major by guess
+pub struct FeedbackMatrix {
+    length: usize,
+    guesses: Vec<String>,
+    secrets: Vec<String>,
+    cells: Cells,
+    /// the row of each guess and the column of each secret
+    rows: HashMap<String, usize>,
+    columns: HashMap<String, usize>,
+}
+
+impl FeedbackMatrix {
+    /// Returns the matrix of `guesses` against `secrets`, all of `length`
+    /// characters, computing the rows in parallel
+    pub fn build(length: usize, guesses: Vec<String>, secrets: Vec<String>) -> FeedbackMatrix {
+        for word in guesses.iter().chain(secrets.iter()) {
+            if word.len() != length {
+                panic!("cannot build a matrix of length {} with '{}'", length, word);
+            }
+        }
+
+        let cells = if length <= NARROW_LENGTH {
+            Cells::Narrow(compute(&guesses, &secrets, |id| id as u16))
+        } else {
+            Cells::Wide(compute(&guesses, &secrets, |id| id))
+        };
+
+        FeedbackMatrix::with_cells(length, guesses, secrets, cells)
+    }
+
+    /// Returns the matrix of every secret of a length against every secret,
+    /// shared by every strategy, or None if it would have more than `FULL_LIMIT`
+    /// cells. It is cached in `directory` when given, and only kept in memory otherwise.
+    /// The lock is released while a matrix is built, so that other lengths stay readable
+    pub fn shared(length: usize, directory: Option<&Path>) -> Option<Arc<FeedbackMatrix>> {
+        if let Some(matrix) = SHARED.lock().unwrap().get(&length) {
+            return matrix.clone();
+        }
+
+        let count = count_secrets(length);
+        let matrix = (count * count <= FULL_LIMIT).then(|| {
+            let secrets = all_secrets(length);
+            Arc::new(match directory {
+                Some(directory) => FeedbackMatrix::load_or_build(length, secrets.clone(), secrets, directory),
+                None => FeedbackMatrix::build(length, secrets.clone(), secrets),
+            })
+        });
+
+        // another thread may have built the same length meanwhile, keep the first one
+        SHARED.lock().unwrap().entry(length).or_insert(matrix).clone()
+    }
+
+    /// Returns the matrix to score `guesses` against `candidates` with on one
+    /// turn: the shared matrix of the length when it fits, or else a matrix
+    /// of just the guesses against the candidates
+    pub fn for_turn(
+        length: usize,
+        guesses: &[String],
+        candidates: &[String],
+        directory: Option<&Path>,
+    ) -> Arc<FeedbackMatrix> {
+        FeedbackMatrix::shared(length, directory)
+            .unwrap_or_else(|| Arc::new(FeedbackMatrix::build(length, guesses.to_vec(), candidates.to_vec())))
+    }
+
+    /// Returns the matrix of cells already computed, indexing its words
+    fn with_cells(length: usize, guesses: Vec<String>, secrets: Vec<String>, cells: Cells) -> FeedbackMatrix {
+        let index = |words: &[String]| words.iter()
+            .enumerate()
+            .map(|(i, word)| (word.clone(), i))
+            .collect();
+
+        FeedbackMatrix {
+            length,
+            rows: index(&guesses),
+            columns: index(&secrets),
+            guesses,
+            secrets,
+            cells,
+        }
+    }
+
+    /// Returns the matrix of `guesses` against `secrets`, reading it from the
+    /// cache in `directory` if present, and building then saving it otherwise.
+    /// Cache files are keyed by the length and a fingerprint of the word lists,
+    /// which is what the active rules decide
+    pub fn load_or_build(
+        length: usize,
+        guesses: Vec<String>,
+        secrets: Vec<String>,
+        directory: &Path,
+    ) -> FeedbackMatrix {
+        let fingerprint = fingerprint(&guesses, &secrets);
+        let path = cache_path(directory, length, fingerprint);
+
+        if let Some(cells) = read_cells(&path, length, guesses.len() * secrets.len(), fingerprint) {
+            return FeedbackMatrix::with_cells(length, guesses, secrets, cells);
+        }
+
+        let matrix = FeedbackMatrix::build(length, guesses, secrets);
+
+        // the cache is optional, so a failed write only costs a rebuild
+        if let Err(error) = matrix.save(&path, fingerprint) {
+            eprintln!("cannot cache the matrix to {}: {}", path.display(), error);
+        }
+
+        matrix
+    }
+
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    pub fn guesses(&self) -> &[String] {
+        &self.guesses
+    }
+
+    pub fn secrets(&self) -> &[String] {
+        &self.secrets
+    }
+
+    /// Returns the pattern id of the `guess`th guess against the `secret`th secret
+    pub fn get(&self, guess: usize, secret: usize) -> u32 {
+        let i = guess * self.secrets.len() + secret;
+        match &self.cells {
+            Cells::Narrow(cells) => cells[i] as u32,
+            Cells::Wide(cells) => cells[i],
+        }
+    }
+
+    /// Returns the pattern id of `guess` against `secret`, read from the matrix
+    /// when it holds both, and computed otherwise
+    pub fn feedback(&self, guess: &str, secret: &str) -> u32 {
+        match (self.row(guess), self.column(secret)) {
+            (Some(row), Some(column)) => self.get(row, column),
+            _ => pattern(secret.as_bytes(), guess.as_bytes()),
+        }
+    }
+
+    /// Returns the row of a guess, if the matrix holds it
+    pub fn row(&self, guess: &str) -> Option<usize> {
+        self.rows.get(guess).copied()
+    }
+
+    /// Returns the column of a secret, if the matrix holds it
+    pub fn column(&self, secret: &str) -> Option<usize> {
+        self.columns.get(secret).copied()
+    }
+
+    /// Returns the number of secrets in each pattern bucket of a guess,
+    /// restricted to the given secret indices
+    pub fn bucket_sizes(&self, guess: usize, secrets: &[usize]) -> HashMap<u32, usize> {
+        let mut sizes = HashMap::new();
+        for secret in secrets.iter() {
+            *sizes.entry(self.get(guess, *secret)).or_insert(0) += 1;
+        }
+
+        sizes
+    }
+
+    /// Writes the matrix to a cache file
+    fn save(&self, path: &Path, fingerprint: u64) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut bytes = vec![];
+        bytes.extend(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(self.length as u8);
+        bytes.extend(fingerprint.to_le_bytes());
+        bytes.extend((self.guesses.len() as u64).to_le_bytes());
+        bytes.extend((self.secrets.len() as u64).to_le_bytes());
+
+        match &self.cells {
+            Cells::Narrow(cells) => cells.iter().for_each(|c| bytes.extend(c.to_le_bytes())),
+            Cells::Wide(cells) => cells.iter().for_each(|c| bytes.extend(c.to_le_bytes())),
+        }
+
+        fs::write(path, bytes)
+    }
+}
+
+/// Returns the pattern ids of every guess against every secret, splitting
+/// the guesses evenly over the available threads
+fn compute<T: Send + Copy + Default>(
+    guesses: &[String],
+    secrets: &[String],
+    narrow: fn(u32) -> T,
+) -> Vec<T> {
+    let mut cells = vec![T::default(); guesses.len() * secrets.len()];
+    if cells.is_empty() {
+        return cells;
+    }
+
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let rows_per_thread = guesses.len().div_ceil(threads);
+
+    thread::scope(|scope| {
+        let chunks = cells.chunks_mut(rows_per_thread * secrets.len());
+        for (chunk, rows) in chunks.zip(guesses.chunks(rows_per_thread)) {
+            scope.spawn(move || {
+                for (row, guess) in chunk.chunks_mut(secrets.len()).zip(rows.iter()) {
+                    for (cell, secret) in row.iter_mut().zip(secrets.iter()) {
+                        *cell = narrow(pattern(secret.as_bytes(), guess.as_bytes()));
+                    }
+                }
+            });
+        }
+    });
+
+    cells
+}
+
+/// Returns the FNV-1a hash of the word lists, stable across builds
+fn fingerprint(guesses: &[String], secrets: &[String]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for list in [guesses, secrets] {
+        for word in list.iter() {
+            // separate the words so that lists cannot collide by concatenation
+            for byte in word.bytes().chain([b'\n']) {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        }
+
+        hash ^= b'|' as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    hash
+}
+
+/// Returns the cache file path for a length and fingerprint
+fn cache_path(directory: &Path, length: usize, fingerprint: u64) -> PathBuf {
+    directory.join(format!("matrix-{}-{:016x}.bin", length, fingerprint))
+}
+
+/// Returns the cells in a cache file, or None if it is missing or does not
+/// match the expected header
+fn read_cells(path: &Path, length: usize, size: usize, fingerprint: u64) -> Option<Cells> {
+    let bytes = fs::read(path).ok()?;
+
+    const HEADER: usize = 4 + 1 + 1 + 8 + 8 + 8;
+    if bytes.len() < HEADER
+        || &bytes[0..4] != MAGIC
+        || bytes[4] != VERSION
+        || bytes[5] as usize != length
+        || u64::from_le_bytes(bytes[6..14].try_into().ok()?) != fingerprint {
+        return None;
+    }
+
+    let rows = u64::from_le_bytes(bytes[14..22].try_into().ok()?) as usize;
+    let columns = u64::from_le_bytes(bytes[22..30].try_into().ok()?) as usize;
+    if rows * columns != size {
+        return None;
+    }
+
+    let body = &bytes[HEADER..];
+    if length <= NARROW_LENGTH {
+        if body.len() != size * 2 {
+            return None;
+        }
+
+        Some(Cells::Narrow(
+            body.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect()
+        ))
+    } else {
+        if body.len() != size * 4 {
+            return None;
+        }
+
+        Some(Cells::Wide(
+            body.chunks_exact(4).map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::set_colors;
+
+    /// Returns a fresh directory under the system temp directory
+    fn temp_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("focdle-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    #[test]
+    fn pattern_agrees_with_set_colors() {
+        let secrets = all_secrets(7);
+        let guesses = ["1+-*%54", "11+11=2", "9*9-9=0", "1+2*3=7", "00000=0", "=======", "3+3+3=9"];
+
+        for secret in secrets.iter().step_by(7) {
+            for guess in guesses.iter().copied().chain(secrets.iter().step_by(301).map(|s| s.as_str())) {
+                assert_eq!(
+                    pattern(secret.as_bytes(), guess.as_bytes()),
+                    pattern_id(set_colors(secret, guess).iter().map(|info| info.color)),
+                    "{} against {}", guess, secret,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn pattern_agrees_with_set_colors_across_lengths() {
+        let guesses = ["1+2*3=7", "12+34*5=182", "99*99*99=970299", "1+1+1=3"];
+
+        for secret in all_secrets(8).iter().step_by(97) {
+            for guess in guesses.iter() {
+                assert_eq!(
+                    pattern(secret.as_bytes(), guess.as_bytes()),
+                    pattern_id(set_colors(secret, guess).iter().map(|info| info.color)),
+                    "{} against {}", guess, secret,
+                );
+            }
+        }
+
+        // the 0 past the end of the guess is still there for a yellow
+        let colors = set_colors("9*9+9=90", "10+1=11");
+        assert_eq!(colors.len(), 7);
+        assert_eq!(colors[0].color, Color::Grey);
+        assert_eq!(colors[1].color, Color::Yellow);
+    }
+
+    #[test]
+    fn pattern_counts_repeated_characters_once() {
+        // the second 1 of the guess is grey, the secret has a single unmatched 1
+        assert_eq!(pattern(b"12+3=15", b"11+1=12"), pattern_id(set_colors("12+3=15", "11+1=12").iter().map(|info| info.color)));
+        // green, yellow, yellow for the first three characters
+        assert_eq!(pattern(b"1+2=3", b"12+=3") % 27, 2 + 3 + 9);
+    }
+
+    #[test]
+    fn cache_round_trips() {
+        let directory = temp_directory("matrix-cache");
+        let secrets: Vec<String> = all_secrets(8).into_iter().step_by(997).collect();
+        let guesses: Vec<String> = secrets.iter().rev().take(10).cloned().collect();
+
+        let built = FeedbackMatrix::load_or_build(8, guesses.clone(), secrets.clone(), &directory);
+        let fingerprint = fingerprint(&guesses, &secrets);
+        assert!(cache_path(&directory, 8, fingerprint).exists());
+
+        let loaded = FeedbackMatrix::load_or_build(8, guesses.clone(), secrets.clone(), &directory);
+        for guess in 0..guesses.len() {
+            for secret in 0..secrets.len() {
+                assert_eq!(built.get(guess, secret), loaded.get(guess, secret));
+            }
+        }
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn cache_rejects_other_word_lists() {
+        let directory = temp_directory("matrix-mismatch");
+        let secrets: Vec<String> = all_secrets(7).into_iter().take(20).collect();
+        let matrix = FeedbackMatrix::build(7, secrets.clone(), secrets.clone());
+
+        let path = cache_path(&directory, 7, 1);
+        matrix.save(&path, 1).unwrap();
+        assert!(read_cells(&path, 7, 400, 1).is_some());
+        assert!(read_cells(&path, 7, 400, 2).is_none());
+        assert!(read_cells(&path, 8, 400, 1).is_none());
+        assert!(read_cells(&path, 7, 399, 1).is_none());
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn turns_build_a_matrix_when_the_full_one_is_too_large() {
+        let candidates: Vec<String> = all_secrets(9).into_iter().step_by(1009).collect();
+        let guesses = vec!["1+-*%==44".to_string(), candidates[0].clone()];
+
+        let matrix = FeedbackMatrix::for_turn(9, &guesses, &candidates, None);
+        assert_eq!((matrix.guesses().len(), matrix.secrets().len()), (2, candidates.len()));
+        for (row, guess) in guesses.iter().enumerate() {
+            for (column, secret) in candidates.iter().enumerate() {
+                assert_eq!(matrix.get(row, column), pattern(secret.as_bytes(), guess.as_bytes()));
+            }
+        }
+
+        // length 7 reads the shared matrix instead
+        let secrets = all_secrets(7);
+        let matrix = FeedbackMatrix::for_turn(7, &secrets[..1], &secrets[..3], None);
+        assert_eq!(matrix.secrets().len(), secrets.len());
+    }
+
+    #[test]
+    fn feedback_computes_words_outside_the_matrix() {
+        let secrets: Vec<String> = all_secrets(7).into_iter().take(5).collect();
+        let matrix = FeedbackMatrix::build(7, secrets.clone(), secrets.clone());
+
+        assert_eq!(matrix.feedback(&secrets[1], &secrets[2]), matrix.get(1, 2));
+        assert_eq!(
+            matrix.feedback("1+-*%54", &secrets[3]),
+            pattern(secrets[3].as_bytes(), b"1+-*%54"),
+        );
+    }
+}
@@ -0,0 +1,206 @@
+//! The multi module plays several boards at once, Quordle style: every
+//! guess goes to every unsolved board, and each board gives its own feedback.
+
+use std::collections::HashSet;
+use crate::config::GuesserConfig;
+use crate::game::{Feedback, Game, GuessError, Rules};
+use crate::matrix::{POOL_SAMPLE, sampled_remaining, SCORE_SAMPLE, spread};
+use crate::session::Session;
+
+/// The `MultiGame` struct is one game per secret, all played with the same guesses
+pub struct MultiGame {
+    boards: Vec<Game>,
+    /// the number of guesses allowed over all boards
+    max_guesses: usize,
+    guesses: usize,
+}
+
+impl MultiGame {
+    /// Returns a game of one board per secret, where `rules.max_guesses`
+    /// is the number of guesses allowed to solve every board. Hard mode is
+    /// refused, as the boards can reveal contradicting hints, leaving no guess
+    /// to play, and so are secrets that are not true equations
+    pub fn new(secrets: &[String], rules: Rules) -> Result<MultiGame, String> {
+        if rules.hard_mode {
+            return Err("--hard does not apply to multi, as the boards can reveal contradicting hints".to_string());
+        }
+
+        let boards = secrets.iter()
+            .map(|secret| Game::new(secret, rules.clone())
+                .map_err(|rejection| format!("invalid secret '{}': {}", secret, rejection)))
+            .collect::<Result<Vec<Game>, String>>()?;
+
+        Ok(MultiGame {
+            boards,
+            max_guesses: rules.max_guesses,
+            guesses: 0,
+        })
+    }
+
+    pub fn boards(&self) -> &[Game] {
+        &self.boards
+    }
+
+    /// Checks and makes a guess on every unsolved board, returning the feedback
+    /// of each board, None for the boards solved before
+    pub fn guess(&mut self, guess: &str) -> Result<Vec<Option<Feedback>>, GuessError> {
+        if self.is_won() || self.is_lost() {
+            return Err(GuessError::GameOver);
+        }
+
+        // every board must accept the guess before any board takes it
+        for board in self.boards.iter().filter(|board| !board.is_won()) {
+            board.check(guess)?;
+        }
+
+        self.guesses += 1;
+        self.boards.iter_mut()
+            .map(|board| if board.is_won() { Ok(None) } else { board.guess(guess).map(Some) })
+            .collect()
+    }
+
+    /// Returns whether every board is solved
+    pub fn is_won(&self) -> bool {
+        self.boards.iter().all(|board| board.is_won())
+    }
+
+    /// Returns whether every allowed guess was made without solving every board
+    pub fn is_lost(&self) -> bool {
+        !self.is_won() && self.guesses >= self.max_guesses
+    }
+}
+
+/// The `MultiStrategy` struct picks guesses for a `MultiGame`, keeping a
+/// session per board and scoring guesses over every unsolved board
+pub struct MultiStrategy {
+    difficulty: usize,
+    sessions: Vec<Session>,
+}
+
+impl MultiStrategy {
+    /// Returns a strategy for a number of boards of a difficulty
+    pub fn new(boards: usize, difficulty: usize, config: &GuesserConfig) -> MultiStrategy {
+        MultiStrategy {
+            difficulty,
+            sessions: (0..boards).map(|_| Session::new(difficulty, config.clone())).collect(),
+        }
+    }
+
+    /// Returns the guess leaving the fewest expected candidates summed over
+    /// the unsolved boards, from the greedy guess of each board and a sample
+    /// of their candidates. A board down to one candidate is solved first
+    pub fn create_guess(&mut self, game: &MultiGame) -> String {
+        let unsolved: Vec<usize> = (0..game.boards().len())
+            .filter(|i| !game.boards()[*i].is_won())
+            .collect();
+
+        for i in unsolved.iter() {
+            self.sessions[*i].sync(self.difficulty, game.boards()[*i].history());
+        }
+
+        // nothing is known yet, so every board would make the same guess
+        if game.boards()[unsolved[0]].history().is_empty() {
+            return self.sessions[unsolved[0]].create_guess();
+        }
+
+        for i in unsolved.iter() {
+            if let [secret] = self.sessions[*i].candidates() {
+                return secret.clone();
+            }
+        }
+
+        let mut pool: Vec<String> = vec![];
+        for i in unsolved.iter() {
+            pool.push(self.sessions[*i].create_guess());
+            pool.extend(spread(self.sessions[*i].candidates(), POOL_SAMPLE).into_iter().cloned());
+        }
+
+        let boards: Vec<(HashSet<&String>, Vec<&String>)> = self.sessions.iter_mut()
+            .enumerate()
+            .filter(|(i, _)| unsolved.contains(i))
+            .map(|(_, session)| {
+                let candidates = session.candidates();
+                (candidates.iter().collect(), spread(candidates, SCORE_SAMPLE))
+            })
+            .collect();
+
+        pool.iter()
+            .map(|guess| {
+                let score: f64 = boards.iter()
+                    .map(|(candidates, sample)| sampled_remaining(guess, sample, candidates.len(), candidates.contains(guess)))
+                    .sum();
+                (score, guess)
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, guess)| guess.clone())
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::set_colors;
+
+    fn secrets() -> Vec<String> {
+        vec!["2*3+4=10".to_string(), "9*8-7=65".to_string()]
+    }
+
+    #[test]
+    fn solved_boards_get_no_feedback() {
+        let mut game = MultiGame::new(&secrets(), Rules::default()).unwrap();
+
+        let feedback = game.guess("2*3+4=10").unwrap();
+        assert_eq!(feedback, vec![Some(set_colors("2*3+4=10", "2*3+4=10")), Some(set_colors("9*8-7=65", "2*3+4=10"))]);
+        assert!(game.boards()[0].is_won());
+
+        let feedback = game.guess("5*3-4=11").unwrap();
+        assert_eq!(feedback, vec![None, Some(set_colors("9*8-7=65", "5*3-4=11"))]);
+        assert_eq!(game.boards()[0].history().len(), 1);
+
+        game.guess("9*8-7=65").unwrap();
+        assert!(game.is_won());
+        assert_eq!(game.guess("9*8-7=65"), Err(GuessError::GameOver));
+    }
+
+    #[test]
+    fn refused_guesses_leave_every_board_untouched() {
+        let rules = Rules { max_guesses: 2, equations_only: true, ..Rules::default() };
+        let mut game = MultiGame::new(&secrets(), rules).unwrap();
+        game.guess("5*3-4=11").unwrap();
+
+        assert!(matches!(game.guess("5*3-4=12"), Err(GuessError::Rejected(_))));
+        assert!(game.boards().iter().all(|board| board.history().len() == 1));
+
+        // the refused guess did not count, so there is one left
+        assert!(!game.is_lost());
+        game.guess("5*3-4=11").unwrap();
+        assert!(game.is_lost());
+        assert_eq!(game.guess("2*3+4=10"), Err(GuessError::GameOver));
+    }
+
+    #[test]
+    fn hard_mode_is_refused() {
+        assert!(MultiGame::new(&secrets(), Rules { hard_mode: true, ..Rules::default() }).is_err());
+    }
+
+    #[test]
+    fn secrets_that_are_not_true_equations_are_refused() {
+        let secrets = vec!["9*8-7=65".to_string(), "12+34=46".to_string()];
+        assert!(MultiGame::new(&secrets, Rules::default()).is_err());
+    }
+
+    #[test]
+    fn the_strategy_solves_two_boards() {
+        let rules = Rules { max_guesses: 10, ..Rules::default() };
+        let mut game = MultiGame::new(&secrets(), rules).unwrap();
+        let mut strategy = MultiStrategy::new(2, 8, &GuesserConfig::default());
+
+        while !game.is_won() && !game.is_lost() {
+            let guess = strategy.create_guess(&game);
+            game.guess(&guess).unwrap();
+        }
+
+        assert!(game.is_won());
+    }
+}
@@ -0,0 +1,246 @@
+//! The play module lets a person play a game in the terminal, reading
+//! guesses from stdin and showing the feedback in ANSI colours.
+
+use std::io::{self, BufRead, Write};
+use crate::game::{AllInfo, CHARS, Color, ColorInfo, create_secret, Game, InfoIndex, Rules};
+use crate::share::{Palette, share_text};
+
+/// ANSI escape that resets the colours
+const RESET: &str = "\x1b[0m";
+
+/// Returns the ANSI escape drawing a tile of a color
+fn paint(color: Color) -> &'static str {
+    match color {
+        Color::Green => "\x1b[30;42m",
+        Color::Yellow => "\x1b[30;43m",
+        Color::Grey => "\x1b[97;100m",
+    }
+}
+
+/// Returns a feedback row as coloured tiles
+fn tiles(feedback: &[ColorInfo]) -> String {
+    feedback.iter()
+        .map(|info| format!("{} {} {}", paint(info.color), info.chara, RESET))
+        .collect()
+}
+
+/// Returns the best color each character has been shown in, None if it was never guessed
+fn best_color(history: &AllInfo, chara: char) -> Option<Color> {
+    let rank = |color: &Color| match color {
+        Color::Green => 2,
+        Color::Yellow => 1,
+        Color::Grey => 0,
+    };
+
+    history.iter()
+        .flatten()
+        .filter(|info| info.chara == chara)
+        .map(|info| info.color)
+        .max_by_key(rank)
+}
+
+/// Returns the keyboard of the 15 characters coloured by their best color,
+/// with the known count of each underneath: exact, at least, or blank if
+/// unknown, and no counts at all without an index
+fn keyboard(history: &AllInfo, index: Option<&InfoIndex>) -> String {
+    let keys: String = CHARS.iter()
+        .map(|c| match best_color(history, *c) {
+            Some(color) => format!("{} {} {}", paint(color), c, RESET),
+            None => format!(" {} ", c),
+        })
+        .collect();
+
+    let Some(index) = index else {
+        return keys;
+    };
+
+    let counts: String = CHARS.iter()
+        .map(|c| {
+            let lookup = &index.lookup[c];
+            let count = if lookup.min == lookup.max {
+                lookup.min.to_string()
+            } else if lookup.min > 0 {
+                format!("{}+", lookup.min)
+            } else {
+                String::new()
+            };
+
+            format!("{:^3}", count)
+        })
+        .collect();
+
+    format!("{}\n{}", keys, counts.trim_end())
+}
+
+/// Returns a game of a difficulty (7-15) with its title, against a random
+/// secret or an adversarial host, or why it cannot be played
+pub fn new_game(difficulty: usize, adversarial: bool, rules: Rules) -> Result<(Game, String), String> {
+    if !(7..=15).contains(&difficulty) {
+        return Err(format!("cannot play a length of {}, expected 7 to 15", difficulty));
+    }
+
+    if adversarial {
+        return Ok((Game::adversarial(difficulty, rules), format!("focdle {} (adversarial)", difficulty)));
+    }
+
+    let secret = create_secret(difficulty);
+    let game = Game::new(&secret, rules)
+        .map_err(|rejection| format!("invalid secret '{}': {}", secret, rejection))?;
+
+    Ok((game, format!("focdle {}", difficulty)))
+}
+
+/// Plays a game against the terminal until it is won, lost or stdin closes,
+/// then prints its share text under `title` when it is over
+pub fn play(mut game: Game, title: &str, palette: Palette) {
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    println!("guess the equation of {} characters", game.secret().len());
+    // lying rows would fold contradicting counts into the index, so there are none
+    let mut index = (game.lies() == 0).then(|| InfoIndex::new(game.secret().len()));
+
+    while !game.is_won() && !game.is_lost() {
+        print!("{} guesses left> ", game.remaining_guesses());
+        // a failed flush only delays the prompt
+        let _ = io::stdout().flush();
+
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+
+        let guess = line.trim();
+        match game.guess(guess) {
+            Ok(feedback) => {
+                if let Some(index) = index.as_mut() {
+                    index.observe(&feedback);
+                }
+                println!("{}", tiles(&feedback));
+                println!("{}", keyboard(game.history(), index.as_ref()));
+            }
+            Err(error) => println!("{}", error),
+        }
+    }
+
+    println!();
+    for feedback in game.history().iter() {
+        println!("{}", tiles(feedback));
+    }
+
+    let guesses = game.history().len();
+    let plural = if guesses == 1 { "guess" } else { "guesses" };
+    if game.is_won() {
+        println!("solved in {} {}", guesses, plural);
+    } else if game.is_lost() {
+        println!("not solved in {} {}, the secret was {}", guesses, plural, game.secret());
+    } else {
+        println!("stopped after {} {}, the secret was {}", guesses, plural, game.secret());
+        return;
+    }
+
+    println!();
+    println!("{}", share_text(title, game.history(), game.is_won(), game.max_guesses(), palette));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{check_secret, set_colors};
+
+    /// Returns a row of the given characters and colors
+    fn row(charas: &str, colors: &[Color]) -> Vec<ColorInfo> {
+        charas.chars()
+            .zip(colors.iter())
+            .enumerate()
+            .map(|(index, (chara, color))| ColorInfo { index, chara, color: *color })
+            .collect()
+    }
+
+    /// Returns the count shown under each of the `CHARS`, blank when unknown
+    fn counts(keyboard: &str) -> Vec<String> {
+        let line: Vec<char> = keyboard.lines().nth(1).unwrap().chars().collect();
+        (0..CHARS.len())
+            .map(|i| line.iter().skip(3 * i).take(3).collect::<String>().trim().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn tiles_paint_each_character_in_its_color() {
+        let feedback = set_colors("9*8-7=65", "13+45=58");
+        let expected: String = feedback.iter()
+            .map(|info| format!("{} {} {}", paint(info.color), info.chara, RESET))
+            .collect();
+
+        assert_eq!(tiles(&feedback), expected);
+        assert!(tiles(&feedback).starts_with("\x1b[97;100m 1 \x1b[0m"));
+        assert_eq!(tiles(&[]), "");
+    }
+
+    #[test]
+    fn best_color_ranks_green_over_yellow_over_grey() {
+        use Color::*;
+        let history = vec![
+            row("1234567", &[Grey, Yellow, Grey, Grey, Grey, Grey, Grey]),
+            row("1234567", &[Yellow, Green, Grey, Grey, Grey, Grey, Grey]),
+            row("1234567", &[Grey, Grey, Green, Grey, Grey, Grey, Grey]),
+        ];
+
+        assert_eq!(best_color(&history, '1'), Some(Yellow));
+        assert_eq!(best_color(&history, '2'), Some(Green));
+        assert_eq!(best_color(&history, '3'), Some(Green));
+        assert_eq!(best_color(&history, '4'), Some(Grey));
+        assert_eq!(best_color(&history, '9'), None);
+        assert_eq!(best_color(&vec![], '1'), None);
+    }
+
+    #[test]
+    fn keyboard_shows_exact_and_least_counts() {
+        // a yellow and a grey 5, a single yellow 8 and a green =
+        let history = vec![set_colors("9*8-7=65", "13+45=58")];
+        let index = InfoIndex::build(8, &history);
+        let keyboard = keyboard(&history, Some(&index));
+        let counts = counts(&keyboard);
+        let count = |c: char| counts[CHARS.iter().position(|key| *key == c).unwrap()].clone();
+
+        assert_eq!(count('5'), "1");
+        assert_eq!(count('1'), "0");
+        assert_eq!(count('='), "1");
+        assert_eq!(count('8'), "1+");
+        assert_eq!(count('9'), "");
+        assert!(keyboard.contains(&format!("{} 8 {}", paint(Color::Yellow), RESET)));
+        assert!(keyboard.contains(" 9 "));
+    }
+
+    #[test]
+    fn keyboard_has_no_counts_without_an_index() {
+        let history = vec![set_colors("9*8-7=65", "13+45=58")];
+        let keyboard = keyboard(&history, None);
+
+        assert_eq!(keyboard.lines().count(), 1);
+        assert!(keyboard.contains(&format!("{} = {}", paint(Color::Green), RESET)));
+    }
+
+    #[test]
+    fn new_games_pick_a_true_equation_of_the_length() {
+        for difficulty in 7..=15 {
+            let rules = Rules { max_guesses: 4, ..Rules::default() };
+            let (game, title) = new_game(difficulty, false, rules).unwrap();
+
+            assert_eq!(game.secret().len(), difficulty);
+            assert_eq!(check_secret(game.secret()), Ok(()));
+            assert_eq!(game.max_guesses(), 4);
+            assert_eq!(title, format!("focdle {}", difficulty));
+        }
+    }
+
+    #[test]
+    fn new_games_refuse_lengths_out_of_range() {
+        assert!(new_game(6, false, Rules::default()).is_err());
+        assert!(new_game(16, true, Rules::default()).is_err());
+
+        let (game, title) = new_game(7, true, Rules::default()).unwrap();
+        assert_eq!(game.secret().len(), 7);
+        assert_eq!(title, "focdle 7 (adversarial)");
+    }
+}
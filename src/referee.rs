@@ -0,0 +1,210 @@
+//! The referee module decides whether a guess may be played under the
+//! active rules, and why not when it is refused.
+
+use std::fmt;
+use crate::game::{AllInfo, CHARS, fast_eval, NUMS, OPERATORS, respects_hints, Rules};
+
+/// The reasons a referee refuses a guess
+#[derive(Debug, PartialEq, Eq)]
+pub enum Rejection {
+    /// the guess is not as long as the secret
+    WrongLength { expected: usize, found: usize },
+    /// the guess is shorter than 7 or longer than 15 characters, in a game
+    /// taking guesses of any length
+    LengthOutOfRange(usize),
+    /// the guess uses a character that is not in `CHARS`
+    UnknownCharacter { position: usize, chara: char },
+    /// the guess does not have exactly one `=`
+    EqualsCount(usize),
+    /// the expression is not three numbers from 1 to 99 joined by two operators,
+    /// going wrong at `position`
+    MalformedExpression { position: usize },
+    /// the result is not a number without leading zeros, going wrong at `position`
+    MalformedResult { position: usize },
+    /// the expression evaluates to `value` (None if undefined) instead of `result`
+    WrongResult { value: Option<i32>, result: i32 },
+    /// the guess ignores a hint revealed by an earlier guess
+    IgnoresHints,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rejection::WrongLength { expected, found } =>
+                write!(f, "the guess has {} characters, expected {}", found, expected),
+            Rejection::LengthOutOfRange(found) =>
+                write!(f, "the guess has {} characters, expected 7 to 15", found),
+            Rejection::UnknownCharacter { position, chara } =>
+                write!(f, "'{}' at position {} is not a focdle character", chara, position),
+            Rejection::EqualsCount(count) =>
+                write!(f, "the guess has {} '=', expected exactly 1", count),
+            Rejection::MalformedExpression { position } =>
+                write!(f, "the expression is malformed at position {}", position),
+            Rejection::MalformedResult { position } =>
+                write!(f, "the result is malformed at position {}", position),
+            Rejection::WrongResult { value: Some(value), result } =>
+                write!(f, "the expression is {}, not {}", value, result),
+            Rejection::WrongResult { value: None, result } =>
+                write!(f, "the expression is undefined, not {}", result),
+            Rejection::IgnoresHints =>
+                write!(f, "the guess ignores a revealed hint"),
+        }
+    }
+}
+
+/// The `Referee` struct checks guesses for a secret length under some rules
+pub struct Referee {
+    length: usize,
+    /// whether guesses must be true equations, rather than any string of `CHARS`
+    equations_only: bool,
+    /// whether guesses may have any length from 7 to 15
+    any_length: bool,
+}
+
+impl Referee {
+    /// Returns the referee of a game with secrets of `length` characters
+    pub fn new(length: usize, rules: &Rules) -> Referee {
+        Referee {
+            length,
+            equations_only: rules.equations_only,
+            any_length: rules.any_length,
+        }
+    }
+
+    /// Returns why `guess` may not be played, if it may not. The guess is
+    /// also held to every hint revealed by the rows of `hints` when given
+    pub fn check(&self, guess: &str, hints: Option<&AllInfo>) -> Result<(), Rejection> {
+        let chars: Vec<char> = guess.chars().collect();
+        if self.any_length && !(7..=15).contains(&chars.len()) {
+            return Err(Rejection::LengthOutOfRange(chars.len()));
+        }
+        if !self.any_length && chars.len() != self.length {
+            return Err(Rejection::WrongLength { expected: self.length, found: chars.len() });
+        }
+
+        if let Some((position, chara)) = chars.iter().enumerate().find(|(_, c)| !CHARS.contains(c)) {
+            return Err(Rejection::UnknownCharacter { position, chara: *chara });
+        }
+
+        if self.equations_only {
+            check_equation(&chars)?;
+        }
+
+        match hints {
+            Some(history) if !respects_hints(guess, history) => Err(Rejection::IgnoresHints),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Returns why the characters of a guess are not a true equation, if they are not
+fn check_equation(chars: &[char]) -> Result<(), Rejection> {
+    let equals: Vec<usize> = chars.iter()
+        .enumerate()
+        .filter(|(_, c)| **c == '=')
+        .map(|(position, _)| position)
+        .collect();
+    if equals.len() != 1 {
+        return Err(Rejection::EqualsCount(equals.len()));
+    }
+
+    let (expression, result) = (&chars[..equals[0]], &chars[equals[0] + 1..]);
+
+    // numbers and operators alternate, starting and ending with a number
+    let mut numbers = 0;
+    let mut position = 0;
+    while position < expression.len() {
+        let run = expression[position..].iter().take_while(|c| NUMS.contains(c)).count();
+        if run == 0 || run > 2 || expression[position] == '0' {
+            return Err(Rejection::MalformedExpression { position });
+        }
+
+        numbers += 1;
+        position += run;
+        if position == expression.len() {
+            break;
+        }
+
+        if numbers == 3 || !OPERATORS.contains(&expression[position]) {
+            return Err(Rejection::MalformedExpression { position });
+        }
+        position += 1;
+    }
+
+    if numbers != 3 {
+        return Err(Rejection::MalformedExpression { position: expression.len() });
+    }
+
+    if result.is_empty() || result[0] == '0' {
+        return Err(Rejection::MalformedResult { position: equals[0] + 1 });
+    }
+    if let Some(offset) = result.iter().position(|c| !NUMS.contains(c)) {
+        return Err(Rejection::MalformedResult { position: equals[0] + 1 + offset });
+    }
+
+    let result: i32 = result.iter().collect::<String>().parse()
+        .map_err(|_| Rejection::MalformedResult { position: equals[0] + 1 })?;
+    let value = fast_eval(&expression.iter().collect::<String>());
+    if value != Some(result) {
+        return Err(Rejection::WrongResult { value, result });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::set_colors;
+
+    fn equations() -> Referee {
+        Referee::new(9, &Rules { equations_only: true, ..Rules::default() })
+    }
+
+    #[test]
+    fn accepts_true_equations() {
+        assert_eq!(equations().check("12+3*4=24", None), Ok(()));
+    }
+
+    #[test]
+    fn rejects_malformed_guesses() {
+        let referee = equations();
+
+        assert_eq!(referee.check("12+3*4=2", None), Err(Rejection::WrongLength { expected: 9, found: 8 }));
+        assert_eq!(referee.check("12+3/4=24", None), Err(Rejection::UnknownCharacter { position: 4, chara: '/' }));
+        assert_eq!(referee.check("12+3*4+24", None), Err(Rejection::EqualsCount(0)));
+        assert_eq!(referee.check("12+3*4==4", None), Err(Rejection::EqualsCount(2)));
+        assert_eq!(referee.check("123*4=492", None), Err(Rejection::MalformedExpression { position: 0 }));
+        assert_eq!(referee.check("1+03*4=13", None), Err(Rejection::MalformedExpression { position: 2 }));
+        assert_eq!(referee.check("1+2*3=007", None), Err(Rejection::MalformedResult { position: 6 }));
+        assert_eq!(referee.check("12+3*4=25", None), Err(Rejection::WrongResult { value: Some(24), result: 25 }));
+    }
+
+    #[test]
+    fn free_guesses_need_only_known_characters() {
+        let referee = Referee::new(9, &Rules::default());
+
+        assert_eq!(referee.check("+++++++++", None), Ok(()));
+        assert_eq!(referee.check("1234567x9", None), Err(Rejection::UnknownCharacter { position: 7, chara: 'x' }));
+    }
+
+    #[test]
+    fn hard_mode_holds_free_guesses_to_the_revealed_hints_only() {
+        let referee = Referee::new(9, &Rules::default());
+        let history = vec![set_colors("12+3*4=24", "1+2*34=69")];
+
+        // not an equation, but keeps the greens and moves every yellow
+        assert_eq!(referee.check("12+3*4=+2", Some(&history)), Ok(()));
+        assert_eq!(referee.check("4+12*3=24", Some(&history)), Err(Rejection::IgnoresHints));
+        assert_eq!(referee.check("12+3*4=96", Some(&history)), Err(Rejection::IgnoresHints));
+    }
+
+    #[test]
+    fn any_length_takes_guesses_from_7_to_15_characters() {
+        let referee = Referee::new(9, &Rules { any_length: true, ..Rules::default() });
+
+        assert_eq!(referee.check("1+1+1=3", None), Ok(()));
+        assert_eq!(referee.check("99*99*99=970299", None), Ok(()));
+        assert_eq!(referee.check("1+1=2", None), Err(Rejection::LengthOutOfRange(5)));
+    }
+}
@@ -0,0 +1,150 @@
+//! The reverse module inverts `set_colors`: given the secret and the colors
+//! of a row, it finds the guesses that could have been made.
+
+use crate::game::{CHARS, Color};
+use crate::matrix::{pattern, pattern_id};
+
+/// Returns the equations among `equations` that give `colors` against `secret`
+pub fn equations_for(secret: &str, colors: &[Color], equations: &[String]) -> Vec<String> {
+    let id = pattern_id(colors.iter().copied());
+    equations.iter()
+        .filter(|guess| pattern(secret.as_bytes(), guess.as_bytes()) == id)
+        .cloned()
+        .collect()
+}
+
+/// Returns up to `limit` strings of `CHARS` that give `colors` against
+/// `secret`, in `CHARS` order, and whether there are more of them
+pub fn guesses_for(secret: &str, colors: &[Color], limit: usize) -> (Vec<String>, bool) {
+    let secret: Vec<char> = secret.chars().collect();
+
+    // the secret characters left for yellows once the greens are taken
+    let mut remaining = [0usize; 128];
+    for (s, color) in secret.iter().zip(colors.iter()) {
+        if *color != Color::Green {
+            remaining[*s as usize] += 1;
+        }
+    }
+
+    let mut found = vec![];
+    let mut guess = vec![];
+    let more = extend(&secret, colors, &mut remaining, &mut guess, &mut found, limit);
+
+    (found, more)
+}
+
+/// Extends a partial guess by every character that gives the next color,
+/// following the left to right yellow assignment of `set_colors`.
+/// Returns whether the limit was hit with guesses left to find
+fn extend(
+    secret: &[char],
+    colors: &[Color],
+    remaining: &mut [usize; 128],
+    guess: &mut Vec<char>,
+    found: &mut Vec<String>,
+    limit: usize,
+) -> bool {
+    let position = guess.len();
+    if position == secret.len() {
+        if found.len() == limit {
+            return true;
+        }
+
+        found.push(guess.iter().collect());
+        return false;
+    }
+
+    for c in CHARS.iter() {
+        let fits = match colors[position] {
+            Color::Green => *c == secret[position],
+            Color::Yellow => *c != secret[position] && remaining[*c as usize] > 0,
+            Color::Grey => *c != secret[position] && remaining[*c as usize] == 0,
+        };
+
+        if !fits {
+            continue;
+        }
+
+        let yellow = colors[position] == Color::Yellow;
+        if yellow {
+            remaining[*c as usize] -= 1;
+        }
+
+        guess.push(*c);
+        let more = extend(secret, colors, remaining, guess, found, limit);
+        guess.pop();
+
+        if yellow {
+            remaining[*c as usize] += 1;
+        }
+
+        if more {
+            return true;
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+    use crate::game::set_colors;
+
+    /// Returns the colors of `guess` against `secret`
+    fn colors(secret: &str, guess: &str) -> Vec<Color> {
+        set_colors(secret, guess).iter().map(|info| info.color).collect()
+    }
+
+    #[test]
+    fn guesses_reproduce_the_row() {
+        let secret = "12+34=46";
+        for guess in ["13+45=58", "9*8-7=65", "11+11=22", "46=34+12"] {
+            let row = colors(secret, guess);
+            let (guesses, _) = guesses_for(secret, &row, 500);
+
+            assert!(guesses.contains(&guess.to_string()) || guesses.len() == 500, "{}", guess);
+            for found in guesses.iter() {
+                assert_eq!(colors(secret, found), row, "{} for {}", found, guess);
+            }
+        }
+    }
+
+    #[test]
+    fn brute_force_finds_nothing_missing() {
+        // every string of five characters, in `CHARS` order, by its row against the secret
+        let secret = "1+1=2";
+        let mut by_row: HashMap<u32, (Vec<Color>, Vec<String>)> = HashMap::new();
+        let mut guess = ['0'; 5];
+        for mut i in 0..CHARS.len().pow(5) {
+            for c in guess.iter_mut().rev() {
+                *c = CHARS[i % CHARS.len()];
+                i /= CHARS.len();
+            }
+
+            let guess: String = guess.iter().collect();
+            let row = colors(secret, &guess);
+            by_row.entry(pattern_id(row.iter().copied())).or_insert((row, vec![])).1.push(guess);
+        }
+
+        for (row, expected) in by_row.values() {
+            let (guesses, more) = guesses_for(secret, row, usize::MAX);
+            assert_eq!(&guesses, expected, "{:?}", row);
+            assert!(!more);
+        }
+
+        // and rows no guess gives have no guesses either, as a single yellow cannot be elsewhere
+        let impossible = vec![Color::Green, Color::Green, Color::Green, Color::Green, Color::Yellow];
+        assert!(!by_row.contains_key(&pattern_id(impossible.iter().copied())));
+        assert_eq!(guesses_for(secret, &impossible, usize::MAX), (vec![], false));
+    }
+
+    #[test]
+    fn the_limit_reports_more_guesses() {
+        let row = colors("12+34=46", "13+45=58");
+        let (guesses, more) = guesses_for("12+34=46", &row, 3);
+        assert_eq!(guesses.len(), 3);
+        assert!(more);
+    }
+}
@@ -0,0 +1,154 @@
+//! The session module keeps what a guesser knows between the turns of a
+//! game, so that each turn only folds in the newest feedback instead of
+//! rebuilding the index and candidates from every past guess.
+
+use crate::config::GuesserConfig;
+use crate::game::{AllInfo, ColorInfo, consistent_secrets, InfoIndex};
+use crate::guesser::Guesser;
+use crate::matrix::{pattern, pattern_id};
+
+/// The `Session` struct carries the index and caches of one game across turns
+pub struct Session {
+    difficulty: usize,
+    config: GuesserConfig,
+    index: InfoIndex,
+    /// every feedback row observed so far
+    info: AllInfo,
+    /// the secrets consistent with `info`, gathered the first time a policy needs them
+    candidates: Option<Vec<String>>,
+    /// number of search nodes visited by the last guess
+    searches: usize,
+}
+
+impl Session {
+    /// Returns a session for a new game of the given difficulty
+    pub fn new(difficulty: usize, config: GuesserConfig) -> Session {
+        Session {
+            difficulty,
+            config,
+            index: InfoIndex::new(difficulty),
+            info: vec![],
+            candidates: None,
+            searches: 0,
+        }
+    }
+
+    pub fn searches(&self) -> usize {
+        self.searches
+    }
+
+    /// Returns the secrets consistent with the observed info, gathering them on first use
+    pub fn candidates(&mut self) -> &[String] {
+        let (difficulty, info) = (self.difficulty, &self.info);
+        self.candidates.get_or_insert_with(|| consistent_secrets(difficulty, info))
+    }
+
+    /// Folds the feedback of one more guess into the index and candidates
+    pub fn observe(&mut self, colors: &[ColorInfo]) {
+        self.index.observe(colors);
+
+        if let Some(candidates) = self.candidates.as_mut() {
+            let guess: String = colors.iter().map(|info| info.chara).collect();
+            let id = pattern_id(colors.iter().map(|info| info.color));
+            candidates.retain(|secret| pattern(secret.as_bytes(), guess.as_bytes()) == id);
+        }
+
+        self.info.push(colors.to_vec());
+    }
+
+    /// Brings the session up to date with the info of a game, observing the
+    /// rows it has not seen yet. Starts over when the info is not a
+    /// continuation of the observed rows, i.e. when a new game has begun
+    pub fn sync(&mut self, difficulty: usize, info: &AllInfo) {
+        let continues = difficulty == self.difficulty
+            && info.len() >= self.info.len()
+            && info.iter().zip(self.info.iter()).all(|(row, seen)| row == seen);
+
+        if !continues {
+            *self = Session::new(difficulty, self.config.clone());
+        }
+
+        for row in info[self.info.len()..].iter() {
+            self.observe(row);
+        }
+    }
+
+    /// Returns the next guess for the observed info
+    pub fn create_guess(&mut self) -> String {
+        let candidates = if !self.info.is_empty() && Guesser::needs_candidates(&self.config, &self.index) {
+            self.candidates().to_vec()
+        } else {
+            vec![]
+        };
+
+        let mut guesser = Guesser::new(self.index.clone(), self.info.len(), candidates, self.config.clone());
+        let guess = guesser.create_guess();
+        self.searches = guesser.searches();
+
+        guess
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::set_colors;
+
+    /// Returns if two indices know the same about the secret
+    fn same_index(a: &InfoIndex, b: &InfoIndex) -> bool {
+        a.difficulty == b.difficulty
+            && a.allowed == b.allowed
+            && a.lookup.iter().all(|(key, lookup)| {
+                let other = &b.lookup[key];
+                (lookup.min, lookup.max, &lookup.correct, &lookup.incorrect)
+                    == (other.min, other.max, &other.correct, &other.incorrect)
+            })
+    }
+
+    fn info(secret: &str, guesses: &[&str]) -> AllInfo {
+        guesses.iter().map(|guess| set_colors(secret, guess)).collect()
+    }
+
+    #[test]
+    fn a_new_game_starts_over() {
+        let mut session = Session::new(8, GuesserConfig::default());
+        session.sync(8, &info("12+34=46", &["13+45=58", "9*8-7=65"]));
+        session.candidates();
+
+        let other = info("9*8-7=65", &["13+45=58"]);
+        session.sync(8, &other);
+        assert_eq!(session.info, other);
+        assert!(same_index(&session.index, &InfoIndex::build(8, &other)));
+        assert_eq!(session.candidates(), consistent_secrets(8, &other));
+
+        // a game of another length starts over too, even with no rows yet
+        session.sync(9, &vec![]);
+        assert!(session.info.is_empty());
+        assert!(same_index(&session.index, &InfoIndex::new(9)));
+    }
+
+    #[test]
+    fn a_continued_game_observes_the_new_rows_only() {
+        let rows = info("12+34=46", &["13+45=58", "9*8-7=65", "12+35=47"]);
+
+        let mut session = Session::new(8, GuesserConfig::default());
+        session.sync(8, &rows[..1].to_vec());
+        let first = session.candidates().to_vec();
+
+        for end in 2..=3 {
+            session.sync(8, &rows[..end].to_vec());
+            assert_eq!(session.info.len(), end);
+            assert!(same_index(&session.index, &InfoIndex::build(8, &rows[..end].to_vec())));
+
+            // the candidates are narrowed from the earlier ones, not gathered again
+            assert!(session.candidates.is_some());
+            let candidates = session.candidates().to_vec();
+            assert!(candidates.iter().all(|secret| first.contains(secret)));
+            assert_eq!(candidates, consistent_secrets(8, &rows[..end].to_vec()));
+        }
+
+        // syncing the same rows again observes nothing
+        session.sync(8, &rows);
+        assert_eq!(session.info.len(), 3);
+    }
+}
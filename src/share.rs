@@ -0,0 +1,107 @@
+//! The share module renders a finished game as spoiler-free text, one row
+//! of coloured squares per guess, to paste wherever the results are shared.
+
+use crate::game::{AllInfo, Color, ColorInfo};
+
+/// The symbols a share grid is drawn with
+#[derive(Clone, Copy, Debug)]
+pub enum Palette {
+    /// 🟩 green, 🟨 yellow and ⬛ grey
+    Emoji,
+    /// 🟧 green, 🟦 yellow and ⬛ grey, for colour blind players
+    HighContrast,
+    /// `G` green, `Y` yellow and `.` grey
+    Ascii,
+}
+
+impl Palette {
+    fn symbol(&self, color: Color) -> &'static str {
+        match (self, color) {
+            (Palette::Emoji, Color::Green) => "🟩",
+            (Palette::Emoji, Color::Yellow) => "🟨",
+            (Palette::HighContrast, Color::Green) => "🟧",
+            (Palette::HighContrast, Color::Yellow) => "🟦",
+            (Palette::Emoji | Palette::HighContrast, Color::Grey) => "⬛",
+            (Palette::Ascii, Color::Green) => "G",
+            (Palette::Ascii, Color::Yellow) => "Y",
+            (Palette::Ascii, Color::Grey) => ".",
+        }
+    }
+}
+
+/// Returns the colors of a share text row in any palette, or None if the
+/// text is not a row. `⬜` is also read as grey, as light mode shares use it
+pub fn parse_row(text: &str) -> Option<Vec<Color>> {
+    let colors: Option<Vec<Color>> = text.trim()
+        .chars()
+        .map(|c| match c {
+            '🟩' | '🟧' | 'G' => Some(Color::Green),
+            '🟨' | '🟦' | 'Y' => Some(Color::Yellow),
+            '⬛' | '⬜' | '.' => Some(Color::Grey),
+            _ => None,
+        })
+        .collect();
+
+    colors.filter(|colors| !colors.is_empty())
+}
+
+/// Returns the row of symbols of one guess
+pub fn share_row(feedback: &[ColorInfo], palette: Palette) -> String {
+    feedback.iter().map(|info| palette.symbol(info.color)).collect()
+}
+
+/// Returns the share text of a game: the title, the score out of the maximum
+/// number of guesses (X if lost), and a row of squares per guess
+pub fn share_text(title: &str, history: &AllInfo, won: bool, max_guesses: usize, palette: Palette) -> String {
+    let score = if won { history.len().to_string() } else { "X".to_string() };
+
+    let mut text = format!("{} {}/{}\n", title, score, max_guesses);
+    for feedback in history.iter() {
+        text.push('\n');
+        text.push_str(&share_row(feedback, palette));
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::set_colors;
+
+    #[test]
+    fn rows_round_trip_in_every_palette() {
+        let history: AllInfo = ["13+45=58", "9*8-7=65", "12+34=46"].iter()
+            .map(|guess| set_colors("12+34=46", guess))
+            .collect();
+
+        for palette in [Palette::Emoji, Palette::HighContrast, Palette::Ascii] {
+            let text = share_text("FoCdle 8", &history, true, 6, palette);
+            let lines: Vec<&str> = text.lines().collect();
+            assert_eq!(lines[0], "FoCdle 8 3/6");
+            assert_eq!(lines[1], "");
+
+            for (line, feedback) in lines[2..].iter().zip(history.iter()) {
+                let colors: Vec<Color> = feedback.iter().map(|info| info.color).collect();
+                assert_eq!(parse_row(line), Some(colors), "{:?} {}", palette, line);
+            }
+            assert_eq!(lines.len(), 2 + history.len());
+        }
+    }
+
+    #[test]
+    fn losses_score_an_x() {
+        let history: AllInfo = vec![set_colors("12+34=46", "13+45=58"); 6];
+        let text = share_text("FoCdle 8", &history, false, 6, Palette::Ascii);
+
+        assert!(text.starts_with("FoCdle 8 X/6\n"), "{}", text);
+        assert_eq!(text.lines().skip(2).collect::<Vec<&str>>(), vec!["GYGY.G.."; 6]);
+    }
+
+    #[test]
+    fn other_text_is_not_a_row() {
+        assert_eq!(parse_row("⬜🟩"), Some(vec![Color::Grey, Color::Green]));
+        assert_eq!(parse_row("  "), None);
+        assert_eq!(parse_row("FoCdle 8 3/6"), None);
+    }
+}
@@ -0,0 +1,142 @@
+//! The summary module renders what an `InfoIndex` knows about the secret,
+//! as a readable text grid or as json.
+
+use std::fmt;
+use serde_json::{json, Value};
+use crate::game::{CHARS, InfoIndex, OPERATORS};
+
+/// The `Summary` struct is a readable snapshot of an `InfoIndex`
+pub struct Summary {
+    /// the characters allowed at each position, in `CHARS` order
+    pub allowed: Vec<Vec<char>>,
+    /// the known (min, max) count of each character, in `CHARS` order
+    pub counts: Vec<(char, usize, usize)>,
+    /// the operators known to be in the secret
+    pub operators: Vec<char>,
+    /// the result lengths the `=` positions still allow, a single one once it is known
+    pub result_lengths: Vec<usize>,
+}
+
+impl Summary {
+    /// Returns the summary of an index
+    pub fn new(index: &InfoIndex) -> Summary {
+        let allowed: Vec<Vec<char>> = index.allowed.iter()
+            .map(|chars| CHARS.iter().filter(|c| chars.contains(c)).copied().collect())
+            .collect();
+
+        let counts = CHARS.iter()
+            .map(|c| (*c, index.lookup[c].min, index.lookup[c].max))
+            .collect();
+
+        let operators = OPERATORS.iter()
+            .filter(|op| index.lookup[op].min > 0)
+            .copied()
+            .collect();
+
+        let result_lengths = allowed.iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, chars)| chars.contains(&'='))
+            .map(|(position, _)| index.difficulty - position - 1)
+            .collect();
+
+        Summary {
+            allowed,
+            counts,
+            operators,
+            result_lengths,
+        }
+    }
+
+    /// Returns the summary as a json object
+    pub fn to_json(&self) -> Value {
+        let counts: serde_json::Map<String, Value> = self.counts.iter()
+            .map(|(c, min, max)| (c.to_string(), json!({"min": min, "max": max})))
+            .collect();
+
+        json!({
+            "allowed": self.allowed.iter()
+                .map(|chars| chars.iter().collect::<String>())
+                .collect::<Vec<String>>(),
+            "counts": counts,
+            "operators": self.operators.iter().map(|op| op.to_string()).collect::<Vec<String>>(),
+            "result_lengths": self.result_lengths,
+        })
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // one row per position, one column per character
+        writeln!(f, "pos {}", CHARS.iter().collect::<String>())?;
+        for (position, chars) in self.allowed.iter().enumerate() {
+            let row: String = CHARS.iter()
+                .map(|c| if chars.contains(c) { *c } else { '.' })
+                .collect();
+            writeln!(f, "{:>3} {}", position, row)?;
+        }
+
+        writeln!(f)?;
+        write!(f, "counts:")?;
+        for (c, min, max) in self.counts.iter() {
+            if min == max {
+                write!(f, " {}={}", c, min)?;
+            } else {
+                write!(f, " {}={}..{}", c, min, max)?;
+            }
+        }
+        writeln!(f)?;
+
+        let operators: String = self.operators.iter().collect();
+        writeln!(f, "operators: {}", if operators.is_empty() { "unknown" } else { &operators })?;
+
+        let lengths: Vec<String> = self.result_lengths.iter().map(|l| l.to_string()).collect();
+        write!(f, "result length: {}", lengths.join(" or "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::set_colors;
+
+    #[test]
+    fn summarises_a_known_secret() {
+        let index = InfoIndex::build(8, &vec![set_colors("12+34=46", "13+45=58")]);
+        let summary = Summary::new(&index);
+
+        let text = summary.to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "pos 0123456789+-*%=");
+        assert_eq!(lines[1], "  0 .1.............");
+        assert_eq!(lines[2], "  1 012.4.67.9.....");
+        assert_eq!(lines[3], "  2 ..........+....");
+        assert_eq!(lines[6], "  5 ..............=");
+        assert!(lines[10].contains(" 1=1..3 "), "{}", lines[10]);
+        assert!(lines[10].contains(" 5=0 ") && lines[10].contains(" 8=0 "), "{}", lines[10]);
+        assert!(lines[10].ends_with(" +=1..2 -=0..1 *=0..1 %=0..1 ==1"), "{}", lines[10]);
+        assert_eq!(lines[11], "operators: +");
+        assert_eq!(lines[12], "result length: 2");
+
+        let json = summary.to_json();
+        assert_eq!(json["allowed"][1], "0124679");
+        assert_eq!(json["allowed"][5], "=");
+        assert_eq!(json["counts"]["1"], json!({"min": 1, "max": 3}));
+        assert_eq!(json["counts"]["5"], json!({"min": 0, "max": 0}));
+        assert_eq!(json["operators"], json!(["+"]));
+        assert_eq!(json["result_lengths"], json!([2]));
+    }
+
+    #[test]
+    fn summarises_a_new_game() {
+        let summary = Summary::new(&InfoIndex::new(8));
+
+        let text = summary.to_string();
+        assert!(text.contains("operators: unknown"), "{}", text);
+        assert!(text.ends_with("result length: 1 or 2"), "{}", text);
+
+        let json = summary.to_json();
+        assert_eq!(json["operators"], json!([]));
+        assert_eq!(json["result_lengths"], json!([1, 2]));
+    }
+}
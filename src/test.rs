@@ -1,79 +1,102 @@
 use std::collections::HashSet;
-use crate::game::{AllInfo, create_secret, set_colors};
+use crate::game::{AllInfo, create_secret, Game, Rules};
 
 
-type CreateGuess = fn(&AllInfo, usize) -> String;
+type CreateGuess<'a> = &'a mut dyn FnMut(&AllInfo, usize) -> String;
 
-/// Test a secret with a given guess function once
+/// How a game played by a guess function ended
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Won,
+    Lost,
+}
+
+/// Test a secret with a given guess function once, returning the outcome
+/// and every guess made. A guess the game refuses loses the game.
+/// Panics if the secret is not a true equation
 pub fn test_once(
     secret: &str,
+    rules: &Rules,
     create_better_guess: CreateGuess,
-) -> (i32, Vec<String>) {
+) -> (Outcome, Vec<String>) {
+    let game = Game::new(secret, rules.clone())
+        .unwrap_or_else(|rejection| panic!("invalid secret '{}': {}", secret, rejection));
+    play_out(game, create_better_guess)
+}
+
+/// Plays a game with a given guess function until it is over, returning the
+/// outcome and every guess made. A guess the game refuses loses the game
+pub fn play_out(mut game: Game, create_better_guess: CreateGuess) -> (Outcome, Vec<String>) {
+    let length = game.secret().len();
     let mut guessed: Vec<String> = vec![];
-    let mut guesses = 0;
-    let mut all_info: AllInfo = vec![];
 
-    loop {
-        let guess = create_better_guess(&all_info, secret.len());
+    while !game.is_won() && !game.is_lost() {
+        let guess = create_better_guess(game.history(), length);
         println!("{}", guess);
 
-        let colors = set_colors(secret, &guess);
-        all_info.push(colors);
-
-        if guess.len() != secret.len() {
-            panic!("length error in guess");
+        if let Err(error) = game.guess(&guess) {
+            eprintln!("invalid guess '{}': {}", guess, error);
+            return (Outcome::Lost, guessed);
         }
 
-        guesses += 1;
-        guessed.push(guess.clone());
-
-        if secret == guess {
-            break;
-        }
+        guessed.push(guess);
     }
 
-    (guesses, guessed)
+    let outcome = if game.is_won() { Outcome::Won } else { Outcome::Lost };
+    (outcome, guessed)
 }
 
 
 pub fn test(
     difficulty: usize,
+    num_secrets: usize,
+    repeats: usize,
+    rules: &Rules,
     create_better_guess: CreateGuess,
 ) {
-    let mut total = 0;
-    let num_secrets = 1000;
-    let n = 100 * num_secrets;
+    let n = repeats * num_secrets;
 
-    let mut all_guesses: Vec<i32> = vec![];
+    // the number of guesses of each won game, and the transcript of each lost one
+    let mut all_guesses: Vec<usize> = vec![];
+    let mut lost: Vec<(String, Vec<String>)> = vec![];
 
     // generate secrets
     for _ in 0..num_secrets {
         let secret = create_secret(difficulty);
         // for each secret, test n times//num_secret times
         for _ in 0..(n / num_secrets) {
-            let (guesses, _guessed) = test_once(&secret, create_better_guess);
-            // add guesses
-            all_guesses.push(guesses);
-            total += guesses;
+            match test_once(&secret, rules, &mut *create_better_guess) {
+                (Outcome::Won, guessed) => all_guesses.push(guessed.len()),
+                (Outcome::Lost, guessed) => lost.push((secret.clone(), guessed)),
+            }
         }
     }
 
     // print data
-    println!("max: {}", all_guesses.iter().max().unwrap());
-    println!("min: {}", all_guesses.iter().min().unwrap());
+    println!("won: {}/{} ({:.1}%)", all_guesses.len(), n, 100.0 * all_guesses.len() as f64 / n as f64);
+
+    if !all_guesses.is_empty() {
+        println!("max: {}", all_guesses.iter().max().unwrap());
+        println!("min: {}", all_guesses.iter().min().unwrap());
 
-    let counts = HashSet::<&i32>::from_iter(all_guesses.iter());
-    let mut counts: Vec<&i32> = Vec::from_iter(counts.into_iter());
-    counts.sort();
+        let counts = HashSet::<&usize>::from_iter(all_guesses.iter());
+        let mut counts: Vec<&usize> = Vec::from_iter(counts);
+        counts.sort();
 
-    for count in counts.into_iter() {
-        // count the number of guesses = count
-        let times = all_guesses.iter()
-            .filter(|c| *c == count)
-            .count();
+        for count in counts.into_iter() {
+            // count the number of guesses = count
+            let times = all_guesses.iter()
+                .filter(|c| *c == count)
+                .count();
 
-        println!("{} = {}", count, times);
+            println!("{} = {}", count, times);
+        }
+
+        let total: usize = all_guesses.iter().sum();
+        println!("average: {:.2}", (total as f64) / (all_guesses.len() as f64));
     }
-    println!("average: {:.2}", (total as f64) / (n as f64));
 
+    for (secret, guessed) in lost.iter() {
+        println!("lost {}: {}", secret, guessed.join(" "));
+    }
 }
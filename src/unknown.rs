@@ -0,0 +1,114 @@
+//! The unknown module solves a secret whose length is not known up front,
+//! in games taking guesses of any length. The feedback of a guess says
+//! something about the secrets of every length, so the solver keeps a single
+//! candidate set across all the lengths, and only learns the length from it.
+
+use std::collections::HashSet;
+use crate::config::GuesserConfig;
+use crate::game::{AllInfo, consistent_secrets_of_any_length, count_secrets};
+use crate::matrix::{pattern, pattern_id, POOL_SAMPLE, sampled_remaining, SCORE_SAMPLE, spread};
+use crate::session::Session;
+
+/// The `UnknownLengthSolver` struct guesses for a secret of any length 7-15,
+/// from feedback that does not need to say which length that is
+pub struct UnknownLengthSolver {
+    config: GuesserConfig,
+    /// the secrets of every length consistent with the rows seen, gathered
+    /// on the first row in a single enumeration of every length
+    candidates: Option<Vec<String>>,
+    /// the number of rows of the history already filtered by
+    seen: usize,
+}
+
+impl UnknownLengthSolver {
+    pub fn new(config: GuesserConfig) -> UnknownLengthSolver {
+        UnknownLengthSolver {
+            config,
+            candidates: None,
+            seen: 0,
+        }
+    }
+
+    /// Returns the lengths the candidates left have, shortest first
+    pub fn lengths(&self) -> Vec<usize> {
+        let mut lengths: Vec<usize> = match &self.candidates {
+            Some(candidates) => candidates.iter()
+                .map(|secret| secret.len())
+                .collect::<HashSet<usize>>()
+                .into_iter()
+                .collect(),
+            None => (7..=15).collect(),
+        };
+
+        lengths.sort();
+        lengths
+    }
+
+    /// Returns the next guess for the rows of a game or a transcript, whose
+    /// guesses may have any length. Without any row, returns the opening guess
+    /// of the length with the most secrets. Otherwise returns the candidate
+    /// splitting a sample of the candidates into the smallest expected group,
+    /// or an error when no secret of any length gives every row
+    pub fn create_guess(&mut self, info: &AllInfo) -> Result<String, String> {
+        if info.is_empty() {
+            let length = (7..=15).max_by_key(|length| count_secrets(*length)).unwrap();
+            return Ok(Session::new(length, self.config.clone()).create_guess());
+        }
+
+        match self.candidates.as_mut() {
+            Some(candidates) => {
+                for row in info[self.seen..].iter() {
+                    let guess: String = row.iter().map(|info| info.chara).collect();
+                    let id = pattern_id(row.iter().map(|info| info.color));
+                    candidates.retain(|secret| pattern(secret.as_bytes(), guess.as_bytes()) == id);
+                }
+            }
+            None => self.candidates = Some(consistent_secrets_of_any_length(info)),
+        }
+        self.seen = info.len();
+
+        let candidates = self.candidates.as_ref().unwrap();
+        let pool = spread(candidates, POOL_SAMPLE);
+        let sample = spread(candidates, SCORE_SAMPLE);
+        pool.into_iter()
+            .map(|guess| (sampled_remaining(guess, &sample, candidates.len(), true), guess))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, guess)| guess.clone())
+            .ok_or_else(|| "no secret of any length from 7 to 15 gives every row so far".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Color, ColorInfo, set_colors};
+
+    #[test]
+    fn transcripts_without_a_length_narrow_every_length() {
+        let secret = "12+3*4=24";
+        let info: AllInfo = ["64%73%==120", "3*42-1=125"].iter()
+            .map(|guess| set_colors(secret, guess))
+            .collect();
+
+        let mut solver = UnknownLengthSolver::new(GuesserConfig::default());
+        let guess = solver.create_guess(&info).unwrap();
+
+        assert!(solver.lengths().contains(&9));
+        assert!(solver.lengths().len() > 1);
+        for row in info.iter() {
+            let guessed: String = row.iter().map(|info| info.chara).collect();
+            assert_eq!(&set_colors(&guess, &guessed), row);
+        }
+    }
+
+    #[test]
+    fn refuses_rows_no_secret_gives() {
+        // every secret has an `=`, so it cannot be grey
+        let row = set_colors("1+1+1=3", "1+1+1=3").into_iter()
+            .map(|info| ColorInfo { color: Color::Grey, ..info })
+            .collect();
+
+        let mut solver = UnknownLengthSolver::new(GuesserConfig::default());
+        assert!(solver.create_guess(&vec![row]).is_err());
+    }
+}